@@ -0,0 +1,154 @@
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use log::{debug, info, warn};
+use reqwest::blocking::Client;
+use std::fs;
+use std::path::Path;
+
+use crate::anilist::model::User;
+use crate::anilist::request;
+use crate::config;
+use crate::error::MendoError;
+use crate::output::{self, Record};
+use crate::tracker;
+use crate::util::{self, MendoConfig};
+
+// Files in the data directory that describe the Anilist account, not the manga
+const USER_FILES: [&str; 2] = ["user.yml", "import_state.yml"];
+
+// Removes the Anilist token and what was cached about its account. Safe to run again.
+pub fn forget_anilist(cfg: &mut MendoConfig) -> Result<()> {
+    cfg.reset_token();
+    config::store(cfg)?;
+    remove_user_files(&cfg.data_dir)
+}
+
+fn remove_user_files(data_dir: &Path) -> Result<()> {
+    for file in &USER_FILES {
+        if util::remove_if_exists(&data_dir.join(file))? {
+            debug!("Removed {}", file);
+        }
+    }
+    Ok(())
+}
+
+// Every other setting, the MyAnimeList client id and series.yml included, stays as it is
+pub fn logout(cfg: &mut MendoConfig, data_dir: &Path, tracker_name: Option<&str>) -> Result<()> {
+    let trackers = match tracker_name {
+        Some(name) => vec![tracker::from_name(name, data_dir)?],
+        None => tracker::all(data_dir),
+    };
+    let mut changed = false;
+    for tracker in &trackers {
+        let name = tracker.name();
+        if tracker.forget(cfg) {
+            changed = true;
+            output::emit(
+                &format!("Logged out of {}.", name),
+                Record {
+                    event: "logged_out",
+                    tracker: Some(name.to_string()),
+                    ..Record::default()
+                },
+            );
+        } else {
+            info!("Not logged in to {}", name);
+        }
+    }
+    if changed {
+        config::store(cfg)?;
+    }
+    // Cleared even without a token, an earlier logout may have stopped halfway
+    if trackers.iter().any(|tracker| tracker.name() == "anilist") {
+        remove_user_files(data_dir)?;
+    }
+    if !changed {
+        output::emit(
+            "Nothing to log out of.",
+            Record {
+                event: "logged_out",
+                ..Record::default()
+            },
+        );
+    }
+    Ok(())
+}
+
+// Asks Anilist who the token belongs to instead of trusting user.yml
+pub fn whoami(cfg: &MendoConfig, data_dir: &Path, client: &Client) -> Result<()> {
+    if !cfg.access_token_is_valid() {
+        return Err(MendoError::Unauthorized.into());
+    }
+    let (viewer, rate_limit) = request::query_viewer(cfg, client)?;
+    let viewer = viewer.ok_or(MendoError::Unauthorized)?;
+
+    // user.yml is written once and then trusted, so an older account can linger in it
+    let user_profile_path = data_dir.join("user.yml");
+    let cached: Option<User> = fs::read_to_string(&user_profile_path)
+        .ok()
+        .and_then(|s| serde_yaml::from_str(&s).ok());
+    match cached {
+        Some(cached) if cached.id != viewer.id => {
+            warn!(
+                "user.yml belonged to `{}` ({}), not to `{}` ({})",
+                cached.name, cached.id, viewer.name, viewer.id
+            );
+            viewer.dump_user_info(&user_profile_path)?;
+            output::emit(
+                &format!(
+                    "user.yml was still about `{}`, it is now refreshed.",
+                    cached.name
+                ),
+                Record {
+                    event: "user_refreshed",
+                    user_id: Some(viewer.id),
+                    user_name: Some(viewer.name.clone()),
+                    message: Some(format!("user.yml belonged to `{}`", cached.name)),
+                    ..Record::default()
+                },
+            );
+        }
+        Some(_) => debug!("user.yml matches the token"),
+        None => viewer.dump_user_info(&user_profile_path)?,
+    }
+
+    let expires_at =
+        util::token_expiry(&cfg.token).and_then(|exp| Utc.timestamp_opt(exp, 0).single());
+    let mut text = format!(
+        "Logged in as `{}` (id {})\n{}",
+        viewer.name, viewer.id, viewer.site_url
+    );
+    if let Some(expires_at) = expires_at {
+        text.push_str(&format!(
+            "\nThe token expires on {} ({} days left)",
+            expires_at.format("%Y-%m-%d"),
+            (expires_at - Utc::now()).num_days()
+        ));
+    }
+    if let (Some(limit), Some(remaining)) = (rate_limit.limit, rate_limit.remaining) {
+        text.push_str(&format!(
+            "\nRate limit: {} of {} requests left this minute",
+            remaining, limit
+        ));
+    }
+    if let Some(reset) = rate_limit.reset {
+        text.push_str(&format!(
+            ", resets in {}s",
+            (reset - Utc::now().timestamp()).max(0)
+        ));
+    }
+    output::emit(
+        &text,
+        Record {
+            event: "whoami",
+            user_id: Some(viewer.id),
+            user_name: Some(viewer.name.clone()),
+            url: Some(viewer.site_url.clone()),
+            expires_at: expires_at.map(|expires_at| expires_at.to_rfc3339()),
+            rate_limit: rate_limit.limit,
+            rate_limit_remaining: rate_limit.remaining,
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
@@ -10,9 +10,33 @@ use yaml_rust::{YamlEmitter, YamlLoader};
 #[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: i32,
-    name: String,
-    site_url: String,
+    pub name: String,
+    pub site_url: String,
     updated_at: i64, // unix timestamp
+    // Missing from user.yml files written by older versions
+    #[serde(default)]
+    pub media_list_options: Option<MediaListOptions>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaListOptions {
+    pub score_format: ScoreFormat,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ScoreFormat {
+    #[serde(rename = "POINT_100")]
+    Point100,
+    #[serde(rename = "POINT_10_DECIMAL")]
+    Point10Decimal,
+    #[serde(rename = "POINT_10")]
+    Point10,
+    #[serde(rename = "POINT_5")]
+    Point5,
+    #[serde(rename = "POINT_3")]
+    Point3,
 }
 
 impl User {
@@ -44,11 +68,33 @@ pub struct MediaTitle {
 pub struct Media {
     #[serde(rename(deserialize = "id"))]
     pub media_id: i32,
+    #[serde(default, rename(deserialize = "idMal"))]
+    pub id_mal: Option<i32>,
     pub status: MediaStatus,
     pub title: MediaTitle,
     pub synonyms: Vec<String>,
     pub chapters: Option<i32>,
     pub volumes: Option<i32>,
+    #[serde(default, rename(deserialize = "coverImage"))]
+    pub cover_image: Option<MediaCoverImage>,
+    // The fields below are only selected by SEARCH_MEDIA and QUERY_MEDIA
+    #[serde(default)]
+    pub format: Option<MediaFormat>,
+    #[serde(default, rename(deserialize = "countryOfOrigin"))]
+    pub country_of_origin: Option<String>,
+    #[serde(default, rename(deserialize = "startDate"))]
+    pub start_date: Option<FuzzyDate>,
+    #[serde(default, rename(deserialize = "siteUrl"))]
+    pub site_url: Option<String>,
+    // Entry of the user on their list, only selected by SEARCH_MEDIA_PAGE with a token
+    #[serde(default, rename(deserialize = "mediaListEntry"))]
+    pub media_list_entry: Option<Box<MediaList>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaCoverImage {
+    pub medium: Option<String>,
+    pub large: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -58,6 +104,45 @@ pub struct MediaList {
     pub entry_id: i32,
     pub status: MediaListStatus,
     pub progress: i32,
+    // The fields below are not selected by every query
+    #[serde(default)]
+    pub progress_volumes: Option<i32>,
+    #[serde(default)]
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub repeat: Option<i32>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub private: Option<bool>,
+    #[serde(default)]
+    pub hidden_from_status_lists: Option<bool>,
+    #[serde(default)]
+    pub started_at: Option<FuzzyDate>,
+    #[serde(default)]
+    pub completed_at: Option<FuzzyDate>,
+    #[serde(default)]
+    pub media: Option<Media>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FuzzyDate {
+    pub year: Option<i32>,
+    pub month: Option<i32>,
+    pub day: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaListGroup {
+    pub name: String,
+    pub is_custom_list: bool,
+    pub entries: Vec<MediaList>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaListCollection {
+    pub lists: Vec<MediaListGroup>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -67,7 +152,7 @@ pub enum MediaType {
     Manga,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum MediaListStatus {
     Current,
@@ -78,6 +163,15 @@ pub enum MediaListStatus {
     Repeating,
 }
 
+// Formats of the manga type, anime formats are never queried
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum MediaFormat {
+    Manga,
+    Novel,
+    OneShot,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum MediaStatus {
@@ -86,6 +180,7 @@ pub enum MediaStatus {
     #[serde(rename = "NOT_YET_RELEASED")]
     NotYetReleased,
     Cancelled,
+    Hiatus,
 }
 
 #[derive(Deserialize, Debug)]
@@ -112,12 +207,54 @@ pub struct MediaResponse {
     pub media: Media,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub total: Option<i32>,
+    pub current_page: i32,
+    pub last_page: Option<i32>,
+    pub has_next_page: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaPage {
+    pub page_info: PageInfo,
+    pub media: Vec<Media>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct MediaPageResponse {
+    pub page: MediaPage,
+}
+
+// Filters of `mendo search`, the unset ones are left out of the query
+#[derive(Debug, Default)]
+pub struct SearchFilters {
+    pub format: Option<MediaFormat>,
+    pub status: Option<MediaStatus>,
+    // ISO 3166-1 alpha-2 code, e.g. `JP` or `KR`
+    pub country: Option<String>,
+    pub from_year: Option<i32>,
+    pub to_year: Option<i32>,
+    pub genres: Vec<String>,
+    // Adult titles are left out unless this is set
+    pub adult: bool,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 pub struct MediaListResponse {
     pub media_list: MediaList,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct MediaListCollectionResponse {
+    pub media_list_collection: MediaListCollection,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SaveMediaListEntry {
@@ -125,4 +262,20 @@ pub struct SaveMediaListEntry {
     pub media_id: Option<i32>,
     pub status: Option<MediaListStatus>,
     pub progress: Option<i32>,
+    pub progress_volumes: Option<i32>,
+    pub score: Option<f64>,
+    pub notes: Option<String>,
+    pub private: Option<bool>,
+    pub hidden_from_status_lists: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Deleted {
+    pub deleted: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct DeleteMediaListEntryResponse {
+    pub delete_media_list_entry: Deleted,
 }
@@ -1,13 +1,22 @@
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use log::{debug, error, info};
 use oauth2::{AuthorizationCode, CsrfToken};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader, Write};
 use std::net::TcpListener;
 use url::Url;
 
+use crate::error::MendoError;
+use crate::output;
 use crate::util::MendoConfig;
 
+const ANILIST_TOKEN_URL: &str = "https://anilist.co/api/v2/oauth/token";
+
+// `listen_for_code` only listens here, so the redirect URL of every tracker has to match it
+pub const CALLBACK_ADDR: &str = "127.0.0.1:8080";
+
 pub fn auth(cfg: &mut MendoConfig) -> Result<String> {
     let client_id = &cfg.id.to_string();
     let client_secret = &cfg.secret;
@@ -26,48 +35,95 @@ pub fn auth(cfg: &mut MendoConfig) -> Result<String> {
 
     let mut post_json = HashMap::new();
     post_json.insert("grant_type", "authorization_code");
-    post_json.insert("client_id", &client_id);
-    post_json.insert("client_secret", &client_secret);
-    post_json.insert("redirect_uri", &redirect_uri);
+    post_json.insert("client_id", client_id);
+    post_json.insert("client_secret", client_secret);
+    post_json.insert("redirect_uri", redirect_uri);
 
     debug!("Setup ready. Attempting to open browser...");
-    println!("Opening browser to authorize...");
+    output::say("Opening browser to authorize...");
 
     open::that(url.to_string())?;
 
+    let (code, returned_state) = listen_for_code()?;
+    // Anything else did not come from the authorization started above
+    if returned_state.as_deref() != Some(state.as_str()) {
+        error!("Anilist redirected with a wrong state");
+        return Err(MendoError::Unauthorized.into());
+    }
+    post_json.insert("code", code.secret());
+
+    debug!("Anilist returned an authorization code");
+    debug!("Now will exchange it for access token...");
+
+    let client = reqwest::blocking::Client::new();
+    let token_res = client
+        .post(ANILIST_TOKEN_URL)
+        .header("Accept", "application/json")
+        .json(&post_json)
+        .send()?
+        .text()?;
+
+    debug!("Anilist returned a token");
+    info!("Successfully authenticated the user!");
+    Ok(token_res)
+}
+
+// Exchanges a made-up code, which Anilist refuses either way. An unknown client id or a
+// wrong secret is refused with `invalid_client` before the code is even looked at.
+pub fn check_client(cfg: &MendoConfig, client: &Client) -> Result<bool> {
+    let res = client
+        .post(ANILIST_TOKEN_URL)
+        .header("Accept", "application/json")
+        .json(&json!({
+            "grant_type": "authorization_code",
+            "client_id": cfg.id.to_string(),
+            "client_secret": cfg.secret,
+            "redirect_uri": cfg.url,
+            "code": "mendo-config-check",
+        }))
+        .send()
+        .map_err(MendoError::Network)?;
+    let status = res.status();
+    let body: Value = res.json().unwrap_or_default();
+    debug!(
+        "Anilist answered the client check with `{}`: {}",
+        status, body
+    );
+    Ok(body.get("error").and_then(Value::as_str) != Some("invalid_client"))
+}
+
+// Shared by every tracker whose redirect URI points at http://localhost:8080/callback.
+// Also returns the `state` of the redirect, for the trackers that check it.
+pub fn listen_for_code() -> Result<(AuthorizationCode, Option<String>)> {
     //Naive way to implement the redirect server
-    let listener = TcpListener::bind("127.0.0.1:8080")?;
+    let listener = TcpListener::bind(CALLBACK_ADDR)?;
     if let Ok((mut stream, _)) = listener.accept() {
         debug!("OK! Found stream!");
 
-        let code;
-        {
-            let mut reader = BufReader::new(&mut stream);
-            let mut request_line = String::new();
-
-            reader.read_line(&mut request_line)?;
-            let redirect_url = request_line
-                .split_whitespace()
-                .nth(1)
-                .expect("Safe because of how anilist defines redirect URI");
-            let url = Url::parse(&format!("http://localhost{}", redirect_url))?;
-
-            let code_pair = url
-                .query_pairs()
-                .find(|pair| {
-                    let &(ref key, _) = pair;
-                    key == "code"
-                })
-                .expect("Something went wrong in the authorization process!");
-
-            let (_, value) = code_pair;
-            code = AuthorizationCode::new(value.into_owned());
-            // It also returns urlState, but we don't care about it.
-        }
-
-        post_json.insert("code", &code.secret());
-
-        let message = "Finished. Return to your terminal!";
+        let mut reader = BufReader::new(&mut stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let redirect_url = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let url = Url::parse(&format!("http://localhost{}", redirect_url))?;
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let code = match query.get("code") {
+            Some(code) => Ok(AuthorizationCode::new(code.clone())),
+            // e.g. `error=access_denied` when the user declines
+            None => {
+                error!(
+                    "The authorization was refused: {}",
+                    query
+                        .get("error")
+                        .map_or("no code returned", String::as_str)
+                );
+                Err(MendoError::Unauthorized)
+            }
+        };
+
+        let message = match code {
+            Ok(_) => "Finished. Return to your terminal!",
+            Err(_) => "Authorization failed. Return to your terminal!",
+        };
         let response = format!(
             "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{}",
             message.len(),
@@ -75,22 +131,9 @@ pub fn auth(cfg: &mut MendoConfig) -> Result<String> {
         );
         stream.write_all(response.as_bytes())?;
 
-        debug!("Anilist returned the following code:\n{}\n", code.secret());
-        debug!("Now will exchange it for access token...");
-
-        let client = reqwest::blocking::Client::new();
-        let token_res = client
-            .post("https://anilist.co/api/v2/oauth/token")
-            .header("Accept", "application/json")
-            .json(&post_json)
-            .send()?
-            .text()?;
-
-        debug!("Anilist returned the following token:\n{}\n", token_res);
-        info!("Successfully authenticated the user!");
-        Ok(token_res)
+        Ok((code?, query.get("state").cloned()))
     } else {
         error!("Could not find stream !?");
-        Err(anyhow!("Something went wrong trying to authorize!"))
+        Err(MendoError::Unauthorized.into())
     }
 }
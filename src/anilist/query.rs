@@ -5,14 +5,105 @@ query {
     name
     siteUrl
     updatedAt
+    mediaListOptions {
+      scoreFormat
+    }
   }
 }
 ";
 
 pub const SEARCH_MEDIA: &str = "
-query ($search: String, $type: MediaType, $status_not: MediaStatus) {
-    Media(search: $search, type: $type, status_not: $status_not) {
+query ($search: String, $type: MediaType, $format_in: [MediaFormat], $status_not: MediaStatus) {
+    Media(search: $search, type: $type, format_in: $format_in, status_not: $status_not) {
+        id
+        status
+        title {
+            romaji
+            english
+            native
+        }
+        synonyms
+        chapters
+        volumes
+        idMal
+        format
+        countryOfOrigin
+        startDate {
+            year
+            month
+            day
+        }
+        siteUrl
+    }
+}
+";
+
+pub const SEARCH_MEDIA_PAGE: &str = "
+query (
+  $page: Int,
+  $perPage: Int,
+  $search: String,
+  $type: MediaType,
+  $format: MediaFormat,
+  $status: MediaStatus,
+  $countryOfOrigin: CountryCode,
+  $startDate_greater: FuzzyDateInt,
+  $startDate_lesser: FuzzyDateInt,
+  $genre_in: [String],
+  $isAdult: Boolean,
+) {
+  Page(page: $page, perPage: $perPage) {
+    pageInfo {
+      total
+      currentPage
+      lastPage
+      hasNextPage
+    }
+    media(
+        search: $search,
+        type: $type,
+        format: $format,
+        status: $status,
+        countryOfOrigin: $countryOfOrigin,
+        startDate_greater: $startDate_greater,
+        startDate_lesser: $startDate_lesser,
+        genre_in: $genre_in,
+        isAdult: $isAdult,
+        sort: SEARCH_MATCH) {
+      id
+      idMal
+      status
+      title {
+        romaji
+        english
+        native
+      }
+      synonyms
+      chapters
+      volumes
+      format
+      countryOfOrigin
+      startDate {
+        year
+        month
+        day
+      }
+      siteUrl
+      mediaListEntry {
+        id
+        status
+        progress
+      }
+    }
+  }
+}
+";
+
+pub const SEARCH_MEDIA_BY_MAL_ID: &str = "
+query ($idMal: Int, $type: MediaType) {
+    Media(idMal: $idMal, type: $type) {
         id
+        idMal
         status
         title {
             romaji
@@ -26,12 +117,47 @@ query ($search: String, $type: MediaType, $status_not: MediaStatus) {
 }
 ";
 
+pub const QUERY_MEDIA: &str = "
+query ($id: Int, $type: MediaType) {
+    Media(id: $id, type: $type) {
+        id
+        idMal
+        status
+        title {
+            romaji
+            english
+            native
+        }
+        synonyms
+        chapters
+        volumes
+        coverImage {
+            medium
+            large
+        }
+        format
+        countryOfOrigin
+        startDate {
+            year
+            month
+            day
+        }
+        siteUrl
+    }
+}
+";
+
 pub const QUERY_MEDIA_LIST: &str = "
 query ($userId: Int, $mediaId: Int, $type: MediaType, $status_not: MediaListStatus) {
     MediaList(userId: $userId, mediaId: $mediaId, type: $type, status_not: $status_not) {
         id
         status
         progress
+        progressVolumes
+        score
+        notes
+        private
+        hiddenFromStatusLists
     }
 }
 ";
@@ -42,16 +168,82 @@ mutation(
   $mediaId: Int,
   $status: MediaListStatus
   $progress: Int,
+  $progressVolumes: Int,
+  $score: Float,
+  $notes: String,
+  $private: Boolean,
+  $hiddenFromStatusLists: Boolean,
 ) {
   SaveMediaListEntry(
       id: $id,
       mediaId: $mediaId,
       status: $status,
-      progress: $progress) {
+      progress: $progress,
+      progressVolumes: $progressVolumes,
+      score: $score,
+      notes: $notes,
+      private: $private,
+      hiddenFromStatusLists: $hiddenFromStatusLists) {
     id
     mediaId
     status
     progress
+    progressVolumes
+    score
+    notes
+    private
+    hiddenFromStatusLists
+  }
+}
+";
+
+pub const DELETE_MEDIA: &str = "
+mutation ($id: Int) {
+  DeleteMediaListEntry(id: $id) {
+    deleted
   }
 }
 ";
+
+pub const QUERY_MEDIA_LIST_COLLECTION: &str = "
+query ($userId: Int, $type: MediaType) {
+    MediaListCollection(userId: $userId, type: $type) {
+        lists {
+            name
+            isCustomList
+            entries {
+                id
+                status
+                progress
+                progressVolumes
+                score(format: POINT_10)
+                repeat
+                notes
+                startedAt {
+                    year
+                    month
+                    day
+                }
+                completedAt {
+                    year
+                    month
+                    day
+                }
+                media {
+                    id
+                    idMal
+                    status
+                    title {
+                        romaji
+                        english
+                        native
+                    }
+                    synonyms
+                    chapters
+                    volumes
+                }
+            }
+        }
+    }
+}
+";
@@ -1,18 +1,25 @@
 use anyhow::{anyhow, Result};
 use log::{debug, error, info, warn};
-use reqwest::{blocking::Client, StatusCode};
+use reqwest::{
+    blocking::{Client, Response},
+    header::HeaderMap,
+    StatusCode,
+};
 use serde::de::DeserializeOwned;
 use serde_json::{json, Map, Value};
-use std::{fs::remove_file, thread, time};
+use std::{thread, time};
 
 use super::model::{
-    MediaListResponse, MediaListStatus, MediaResponse, MediaStatus, MediaType, QueryResponse,
-    SaveMediaListEntry, ViewerResponse,
+    DeleteMediaListEntryResponse, MediaFormat, MediaListCollectionResponse, MediaListResponse,
+    MediaListStatus, MediaPageResponse, MediaResponse, MediaStatus, MediaType, QueryResponse,
+    SaveMediaListEntry, SearchFilters, User, ViewerResponse,
+};
+use super::query::{
+    DELETE_MEDIA, QUERY_MEDIA, QUERY_MEDIA_LIST, QUERY_MEDIA_LIST_COLLECTION, QUERY_USER,
+    SEARCH_MEDIA, SEARCH_MEDIA_BY_MAL_ID, SEARCH_MEDIA_PAGE, UPDATE_MEDIA,
 };
-use super::query::{QUERY_MEDIA_LIST, QUERY_USER, SEARCH_MEDIA, UPDATE_MEDIA};
-use crate::util;
+use crate::error::MendoError;
 use crate::util::MendoConfig;
-use crate::PROGRAM_NAME;
 
 const ANILIST_API_URL: &str = "https://graphql.anilist.co";
 
@@ -49,7 +56,7 @@ where
             cl = cl.header("Authorization", format!("Bearer {}", token));
         }
 
-        let res = cl.json(&query).send()?;
+        let res = cl.json(&query).send().map_err(MendoError::Network)?;
 
         let res_status = res.status();
 
@@ -67,53 +74,56 @@ where
             }
             StatusCode::UNAUTHORIZED => {
                 error!("Anilist returned code `{}'. Unauthorized!", res_status);
-                let response: QueryResponse<R> = res.json()?;
-                debug!("Response =\n{:#?}", response);
-                debug!("Deleting the existing token to force user to reauth...");
-                let user_profile_path = util::get_data_dir("", "", PROGRAM_NAME)?.join("user.yml");
-                remove_file(&user_profile_path)?;
-                confy::store(PROGRAM_NAME, None, MendoConfig::default())?;
-                return Err(anyhow!("Unthorized! Run the program again to reauthorize!"));
+                // Only `logout` and `auth --force` drop the token, an expired one is replaced
+                // by the next `auth`
+                match parse_response::<R>(res) {
+                    Ok(response) => debug!("Response =\n{:#?}", response),
+                    Err(e) => debug!("Could not read the response: {}", e),
+                }
+                return Err(MendoError::Unauthorized.into());
             }
             // This could happen in two situations:
             // 1. The user has not created entry for this title.
             // 2. The input filename is just garbage, could not search for that title
             StatusCode::NOT_FOUND => {
                 warn!("Anilist returned `{}'!", res.status());
-                if let Some(vars) = &variables {
-                    // This is the 1st situation AKA when using query_media_list
-                    if vars.contains_key("mediaId") {
-                        debug!("It seems like user has not created entry for this title!");
-                        let media_id = vars
-                            .get("mediaId")
-                            .expect("Safe because of the above check")
-                            .as_u64()
-                            .expect("Safe because of how mendo defined media_id")
-                            as i32;
-                        debug!("Got media_id `{}` from variables", media_id);
-                        create_new_entry(cfg, media_id, MediaListStatus::Current, 0, client)?;
-                        info!("Will now retry to query MediaList...");
-                        let result =
-                            query_graphql(QUERY_MEDIA_LIST, &variables, cfg, &client, false)?;
-                        return Ok(result);
-                    // This is the 2ns situation AKA when using search_media
-                    } else {
-                        error!("The API did not return any result! Maybe recheck your archive filename?");
-                        return Err(anyhow!("The API did not return any result! Maybe recheck your archive filename?"));
-                    }
+                let vars = variables.as_ref();
+                // This is the 1st situation AKA when using query_media_list
+                if let Some(media_id) = vars
+                    .filter(|_| query_str == QUERY_MEDIA_LIST)
+                    .and_then(|vars| vars.get("mediaId"))
+                    .and_then(|media_id| media_id.as_i64())
+                {
+                    debug!("It seems like user has not created entry for this title!");
+                    return Err(MendoError::NoListEntry(media_id as i32).into());
                 }
+                // This is the 2nd situation AKA when using search_media
+                let search = vars
+                    .and_then(|vars| vars.get("search").or_else(|| vars.get("idMal")))
+                    .map_or_else(|| "the title".to_string(), |search| search.to_string());
+                error!("The API did not return any result! Maybe recheck your archive filename?");
+                return Err(MendoError::NotFound(search.trim_matches('"').to_string()).into());
             }
             StatusCode::OK => {
                 info!("Anilist returned `{}'!", res.status());
-                let response: QueryResponse<R> = res.json()?;
+                let response: QueryResponse<R> = parse_response(res)?;
                 debug!("Response =\n{:#?}", response);
-                return Ok(response);
+                return match response {
+                    QueryResponse {
+                        data: None,
+                        errors: Some(errors),
+                    } => Err(MendoError::GraphQL(errors).into()),
+                    response => Ok(response),
+                };
             }
             _ => {
-                let response: QueryResponse<R> = res.json()?;
+                let response: QueryResponse<R> = parse_response(res)?;
                 debug!("Response =\n{:#?}", response);
                 error!("Anilist returned an unimplemented code `{}'!", res_status);
-                return Err(anyhow!("Anilist returned an unimplemented response code!"));
+                return match response.errors {
+                    Some(errors) => Err(MendoError::GraphQL(errors).into()),
+                    None => Err(anyhow!("Anilist returned an unimplemented response code!")),
+                };
             }
         }
     }
@@ -122,53 +132,205 @@ where
         "Exceeded the local rate limit count ({})",
         local_rate_limit_count
     );
-    Err(anyhow!(
-        "Exceeded the rate limit count ({})",
-        local_rate_limit_count
-    ))
+    Err(MendoError::RateLimited(local_rate_limit_count).into())
+}
+
+fn parse_response<R: DeserializeOwned>(res: Response) -> Result<QueryResponse<R>> {
+    res.json()
+        .map_err(|e| MendoError::Parse(format!("the Anilist response: {}", e)).into())
 }
 
 pub fn query_user(cfg: &mut MendoConfig, client: &Client) -> Result<QueryResponse<ViewerResponse>> {
     info!("Querying for info of currently authenticated user...");
-    query_graphql(QUERY_USER, &None, cfg, &client, true)
+    query_graphql(QUERY_USER, &None, cfg, client, true)
+}
+
+// What Anilist tells about the rate limit in the headers of every response
+#[derive(Debug, Default)]
+pub struct RateLimit {
+    pub limit: Option<u32>,
+    pub remaining: Option<u32>,
+    // Unix timestamp, only sent once the limit is reached
+    pub reset: Option<i64>,
+}
+
+impl RateLimit {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        RateLimit {
+            limit: header("X-RateLimit-Limit").and_then(|value| value.parse().ok()),
+            remaining: header("X-RateLimit-Remaining").and_then(|value| value.parse().ok()),
+            reset: header("X-RateLimit-Reset").and_then(|value| value.parse().ok()),
+        }
+    }
+}
+
+// Unlike `query_user`, a refused token is only reported and stays in the config file.
+// The user is None when Anilist refuses the token.
+pub fn query_viewer(cfg: &MendoConfig, client: &Client) -> Result<(Option<User>, RateLimit)> {
+    let res = client
+        .post(ANILIST_API_URL)
+        .header("Accept", "application/json")
+        .bearer_auth(cfg.token.as_ref())
+        .json(&json!({ "query": QUERY_USER }))
+        .send()
+        .map_err(MendoError::Network)?;
+    let rate_limit = RateLimit::from_headers(res.headers());
+    debug!("Anilist rate limit: {:?}", rate_limit);
+    if !res.status().is_success() {
+        debug!("Anilist refused the token with `{}`", res.status());
+        return Ok((None, rate_limit));
+    }
+    let response: QueryResponse<ViewerResponse> = parse_response(res)?;
+    Ok((
+        response.data.map(|viewer_resp| viewer_resp.viewer),
+        rate_limit,
+    ))
+}
+
+// Returns the name of the user when Anilist accepts the token
+pub fn check_token(cfg: &MendoConfig, client: &Client) -> Result<Option<String>> {
+    Ok(query_viewer(cfg, client)?.0.map(|user| user.name))
 }
 
+// An empty `format_in` searches every format of `media_type`
 pub fn search_media(
     cfg: &mut MendoConfig,
     search_string: &str,
     media_type: MediaType,
+    format_in: &[MediaFormat],
     client: &Client,
 ) -> Result<QueryResponse<MediaResponse>> {
-    let variables = json!({
+    let mut variables = json!({
         "search": search_string,
         "type": media_type,
         "status_not": MediaStatus::NotYetReleased,
     });
+    if !format_in.is_empty() {
+        variables["format_in"] = json!(format_in);
+    }
 
     if let serde_json::Value::Object(variables) = variables {
         info!(
-            "Searching Media using name: `{}`, type: `{:?}`...",
-            search_string, media_type
+            "Searching Media using name: `{}`, type: `{:?}`, formats: `{:?}`...",
+            search_string, media_type, format_in
         );
-        query_graphql(SEARCH_MEDIA, &Some(variables), cfg, &client, false)
+        query_graphql(SEARCH_MEDIA, &Some(variables), cfg, client, false)
     } else {
         error!("Media list query variables is not a json object");
         Err(anyhow!("Media list query variables is not a json object"))
     }
 }
 
+// Unlike `search_media`, returns every match and sends the token if there is one,
+// so the results tell whether they are on the list already
+pub fn search_media_page(
+    cfg: &mut MendoConfig,
+    search_string: &str,
+    filters: &SearchFilters,
+    page: i32,
+    per_page: i32,
+    client: &Client,
+) -> Result<QueryResponse<MediaPageResponse>> {
+    let mut variables = Map::new();
+    variables.insert("search".to_string(), json!(search_string));
+    variables.insert("type".to_string(), json!(MediaType::Manga));
+    variables.insert("page".to_string(), json!(page));
+    variables.insert("perPage".to_string(), json!(per_page));
+    if let Some(format) = filters.format {
+        variables.insert("format".to_string(), json!(format));
+    }
+    if let Some(status) = &filters.status {
+        variables.insert("status".to_string(), json!(status));
+    }
+    if let Some(country) = &filters.country {
+        variables.insert("countryOfOrigin".to_string(), json!(country));
+    }
+    // FuzzyDateInt is YYYYMMDD, and 00 stands for an unknown month or day
+    if let Some(from_year) = filters.from_year {
+        variables.insert(
+            "startDate_greater".to_string(),
+            json!(from_year * 10000 - 1),
+        );
+    }
+    if let Some(to_year) = filters.to_year {
+        variables.insert("startDate_lesser".to_string(), json!((to_year + 1) * 10000));
+    }
+    if !filters.genres.is_empty() {
+        variables.insert("genre_in".to_string(), json!(filters.genres));
+    }
+    if !filters.adult {
+        variables.insert("isAdult".to_string(), json!(false));
+    }
+
+    info!(
+        "Searching page {} of Media using name: `{}`, filters: {:?}...",
+        page, search_string, filters
+    );
+    let use_token = cfg.access_token_is_valid();
+    query_graphql(SEARCH_MEDIA_PAGE, &Some(variables), cfg, client, use_token)
+}
+
+pub fn search_media_by_mal_id(
+    cfg: &mut MendoConfig,
+    id_mal: i32,
+    media_type: MediaType,
+    client: &Client,
+) -> Result<QueryResponse<MediaResponse>> {
+    let variables = json!({
+        "idMal": id_mal,
+        "type": media_type,
+    });
+
+    if let serde_json::Value::Object(variables) = variables {
+        info!(
+            "Searching Media using MyAnimeList ID: `{}`, type: `{:?}`...",
+            id_mal, media_type
+        );
+        query_graphql(SEARCH_MEDIA_BY_MAL_ID, &Some(variables), cfg, client, false)
+    } else {
+        error!("Media query variables is not a json object");
+        Err(anyhow!("Media query variables is not a json object"))
+    }
+}
+
+pub fn query_media(
+    cfg: &mut MendoConfig,
+    media_id: i32,
+    client: &Client,
+) -> Result<QueryResponse<MediaResponse>> {
+    let variables = json!({
+        "id": media_id,
+        "type": MediaType::Manga,
+    });
+
+    if let serde_json::Value::Object(variables) = variables {
+        info!("Querying Media with ID: `{}`...", media_id);
+        query_graphql(QUERY_MEDIA, &Some(variables), cfg, client, false)
+    } else {
+        error!("Media query variables is not a json object");
+        Err(anyhow!("Media query variables is not a json object"))
+    }
+}
+
 pub fn query_media_list(
     cfg: &mut MendoConfig,
     user_id: i32,
     media_id: i32,
     media_type: MediaType,
+    status_not: Option<MediaListStatus>,
     client: &Client,
 ) -> Result<QueryResponse<MediaListResponse>> {
     let variables = json!({
         "userId": user_id,
         "mediaId": media_id,
         "type": media_type,
-        "status_not": MediaListStatus::Dropped,
+        "status_not": status_not,
     });
 
     if let serde_json::Value::Object(variables) = variables {
@@ -176,13 +338,44 @@ pub fn query_media_list(
             "Querying MediaList for progress using media ID: `{}`, type: `{:?}` of user...",
             media_id, media_type
         );
-        query_graphql(QUERY_MEDIA_LIST, &Some(variables), cfg, &client, false)
+        query_graphql(QUERY_MEDIA_LIST, &Some(variables), cfg, client, false)
     } else {
         error!("Media list query variables is not a json object");
         Err(anyhow!("Media list query variables is not a json object"))
     }
 }
 
+pub fn query_media_list_collection(
+    cfg: &mut MendoConfig,
+    user_id: i32,
+    media_type: MediaType,
+    client: &Client,
+) -> Result<QueryResponse<MediaListCollectionResponse>> {
+    let variables = json!({
+        "userId": user_id,
+        "type": media_type,
+    });
+
+    if let serde_json::Value::Object(variables) = variables {
+        info!(
+            "Querying the whole MediaListCollection of type: `{:?}` of user...",
+            media_type
+        );
+        query_graphql(
+            QUERY_MEDIA_LIST_COLLECTION,
+            &Some(variables),
+            cfg,
+            client,
+            true,
+        )
+    } else {
+        error!("Media list collection query variables is not a json object");
+        Err(anyhow!(
+            "Media list collection query variables is not a json object"
+        ))
+    }
+}
+
 pub fn update_media(
     cfg: &mut MendoConfig,
     entry_id: i32,
@@ -199,13 +392,169 @@ pub fn update_media(
             "Updating progress of title which has entry ID: `{}` with: progress `{}` for user...",
             entry_id, progress
         );
-        query_graphql(UPDATE_MEDIA, &Some(variables), cfg, &client, true)
+        query_graphql(UPDATE_MEDIA, &Some(variables), cfg, client, true)
+    } else {
+        error!("Media list query variables is not a json object");
+        Err(anyhow!("Media list query variables is not a json object"))
+    }
+}
+
+pub fn update_media_volumes(
+    cfg: &mut MendoConfig,
+    entry_id: i32,
+    progress: i32,
+    progress_volumes: i32,
+    client: &Client,
+) -> Result<QueryResponse<SaveMediaListEntry>> {
+    let variables = json!({
+        "id": entry_id,
+        "progress": progress,
+        "progressVolumes": progress_volumes,
+    });
+
+    if let serde_json::Value::Object(variables) = variables {
+        info!(
+            "Updating progress of title which has entry ID: `{}` with: progress `{}`, progressVolumes `{}` for user...",
+            entry_id, progress, progress_volumes
+        );
+        query_graphql(UPDATE_MEDIA, &Some(variables), cfg, client, true)
+    } else {
+        error!("Media list query variables is not a json object");
+        Err(anyhow!("Media list query variables is not a json object"))
+    }
+}
+
+pub fn complete_media(
+    cfg: &mut MendoConfig,
+    entry_id: i32,
+    progress: i32,
+    progress_volumes: Option<i32>,
+    client: &Client,
+) -> Result<QueryResponse<SaveMediaListEntry>> {
+    let mut variables = Map::new();
+    variables.insert("id".to_string(), json!(entry_id));
+    variables.insert("status".to_string(), json!(MediaListStatus::Completed));
+    variables.insert("progress".to_string(), json!(progress));
+    if let Some(progress_volumes) = progress_volumes {
+        variables.insert("progressVolumes".to_string(), json!(progress_volumes));
+    }
+
+    info!(
+        "Completing title which has entry ID: `{}` with: progress `{}`, progressVolumes `{:?}` for user...",
+        entry_id, progress, progress_volumes
+    );
+    query_graphql(UPDATE_MEDIA, &Some(variables), cfg, client, true)
+}
+
+// Sets status and progress back to what they were, e.g. to undo an update
+pub fn restore_media(
+    cfg: &mut MendoConfig,
+    entry_id: i32,
+    status: Option<&MediaListStatus>,
+    progress: i32,
+    progress_volumes: i32,
+    client: &Client,
+) -> Result<QueryResponse<SaveMediaListEntry>> {
+    let mut variables = Map::new();
+    variables.insert("id".to_string(), json!(entry_id));
+    if let Some(status) = status {
+        variables.insert("status".to_string(), json!(status));
+    }
+    variables.insert("progress".to_string(), json!(progress));
+    variables.insert("progressVolumes".to_string(), json!(progress_volumes));
+
+    info!(
+        "Restoring title which has entry ID: `{}` to: status `{:?}`, progress `{}`, progressVolumes `{}` for user...",
+        entry_id, status, progress, progress_volumes
+    );
+    query_graphql(UPDATE_MEDIA, &Some(variables), cfg, client, true)
+}
+
+pub fn delete_media(
+    cfg: &mut MendoConfig,
+    entry_id: i32,
+    client: &Client,
+) -> Result<QueryResponse<DeleteMediaListEntryResponse>> {
+    let mut variables = Map::new();
+    variables.insert("id".to_string(), json!(entry_id));
+
+    info!(
+        "Deleting title which has entry ID: `{}` from the list of user...",
+        entry_id
+    );
+    query_graphql(DELETE_MEDIA, &Some(variables), cfg, client, true)
+}
+
+pub fn update_score(
+    cfg: &mut MendoConfig,
+    entry_id: i32,
+    score: f64,
+    client: &Client,
+) -> Result<QueryResponse<SaveMediaListEntry>> {
+    let variables = json!({
+        "id": entry_id,
+        "score": score,
+    });
+
+    if let serde_json::Value::Object(variables) = variables {
+        info!(
+            "Updating score of title which has entry ID: `{}` with: score `{}` for user...",
+            entry_id, score
+        );
+        query_graphql(UPDATE_MEDIA, &Some(variables), cfg, client, true)
     } else {
         error!("Media list query variables is not a json object");
         Err(anyhow!("Media list query variables is not a json object"))
     }
 }
 
+pub fn update_notes(
+    cfg: &mut MendoConfig,
+    entry_id: i32,
+    notes: &str,
+    client: &Client,
+) -> Result<QueryResponse<SaveMediaListEntry>> {
+    let variables = json!({
+        "id": entry_id,
+        "notes": notes,
+    });
+
+    if let serde_json::Value::Object(variables) = variables {
+        info!(
+            "Updating notes of title which has entry ID: `{}` for user...",
+            entry_id
+        );
+        query_graphql(UPDATE_MEDIA, &Some(variables), cfg, client, true)
+    } else {
+        error!("Media list query variables is not a json object");
+        Err(anyhow!("Media list query variables is not a json object"))
+    }
+}
+
+// Fields left as None are not sent, so Anilist keeps their current value
+pub fn update_visibility(
+    cfg: &mut MendoConfig,
+    entry_id: i32,
+    private: Option<bool>,
+    hidden_from_status_lists: Option<bool>,
+    client: &Client,
+) -> Result<QueryResponse<SaveMediaListEntry>> {
+    let mut variables = Map::new();
+    variables.insert("id".to_string(), json!(entry_id));
+    if let Some(private) = private {
+        variables.insert("private".to_string(), json!(private));
+    }
+    if let Some(hidden) = hidden_from_status_lists {
+        variables.insert("hiddenFromStatusLists".to_string(), json!(hidden));
+    }
+
+    info!(
+        "Updating visibility of title which has entry ID: `{}` with: private `{:?}`, hiddenFromStatusLists `{:?}` for user...",
+        entry_id, private, hidden_from_status_lists
+    );
+    query_graphql(UPDATE_MEDIA, &Some(variables), cfg, client, true)
+}
+
 pub fn create_new_entry(
     cfg: &mut MendoConfig,
     media_id: i32,
@@ -224,7 +573,7 @@ pub fn create_new_entry(
         info!("Creating entry for title which has media ID: `{}` with: status `{:?}`, progress `{}` for user...",
             media_id, status, progress
         );
-        query_graphql(UPDATE_MEDIA, &Some(variables), cfg, &client, true)
+        query_graphql(UPDATE_MEDIA, &Some(variables), cfg, client, true)
     } else {
         error!("Media list query variables is not a json object");
         Err(anyhow!("Media list query variables is not a json object"))
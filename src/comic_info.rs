@@ -0,0 +1,249 @@
+use anyhow::Result;
+use log::{debug, warn};
+use regex::Regex;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::util;
+
+const COMIC_INFO: &str = "ComicInfo.xml";
+
+// Nothing in Rust reads RAR, so cbr and cb7 are left to whichever of these is installed.
+// Each prints the named entry of the archive to stdout, so unlike in CBZ archives only a
+// `ComicInfo.xml` at the root is found there.
+const EXTRACTORS: [(&str, &[&str]); 3] = [
+    ("bsdtar", &["-xOf"]),
+    ("7z", &["e", "-so"]),
+    ("unrar", &["p", "-inul"]),
+];
+
+// The fields of ComicInfo.xml that tell which series and chapter an archive is
+#[derive(Debug, Default, Clone)]
+pub struct ComicInfo {
+    pub series: Option<String>,
+    pub volume: Option<i32>,
+    // Text, since it can be `12.5` or `Extra` as well
+    pub number: Option<String>,
+    // From the first Anilist and MyAnimeList URL in `Web`, which can hold several of them
+    // separated by spaces or commas
+    pub anilist_id: Option<i32>,
+    pub mal_id: Option<i32>,
+}
+
+// Both sites in one pattern, so it is compiled once per archive
+fn ids_from_web(web: &str) -> (Option<i32>, Option<i32>) {
+    let web_re = Regex::new(r"(anilist\.co|myanimelist\.net)/manga/(\d+)").expect("Valid regex");
+    let (mut anilist_id, mut mal_id) = (None, None);
+    for caps in web_re.captures_iter(web) {
+        let id = match caps[2].parse().ok() {
+            Some(id) => id,
+            None => continue,
+        };
+        match &caps[1] {
+            "anilist.co" => anilist_id = anilist_id.or(Some(id)),
+            _ => mal_id = mal_id.or(Some(id)),
+        }
+    }
+    (anilist_id, mal_id)
+}
+
+fn parse(xml: &str) -> Result<Option<ComicInfo>> {
+    let mut records = util::read_xml_records(xml, "ComicInfo")?;
+    if records.is_empty() {
+        return Ok(None);
+    }
+    let mut record = records.swap_remove(0);
+    let mut take = |field: &str| {
+        record
+            .remove(field)
+            .filter(|value| !value.trim().is_empty())
+    };
+    let (anilist_id, mal_id) = take("Web").map_or((None, None), |web| ids_from_web(&web));
+    Ok(Some(ComicInfo {
+        series: take("Series").map(|series| series.trim().to_string()),
+        volume: take("Volume").and_then(|volume| volume.trim().parse().ok()),
+        number: take("Number").map(|number| number.trim().to_string()),
+        anilist_id,
+        mal_id,
+    }))
+}
+
+// Taggers put ComicInfo.xml at the root, but some nest it in the folder of the chapter,
+// so every entry is looked at, whatever the case of its name
+fn read_zip(path: &Path) -> Result<Option<String>> {
+    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let is_comic_info = matches!(
+            Path::new(file.name()).file_name().and_then(|name| name.to_str()),
+            Some(name) if name.eq_ignore_ascii_case(COMIC_INFO)
+        );
+        if is_comic_info {
+            let mut xml = String::new();
+            file.read_to_string(&mut xml)?;
+            return Ok(Some(xml));
+        }
+    }
+    Ok(None)
+}
+
+fn read_with_extractor(path: &Path) -> Option<String> {
+    for (program, args) in EXTRACTORS.iter() {
+        let output = Command::new(program)
+            .args(*args)
+            .arg(path)
+            .arg(COMIC_INFO)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() && !output.stdout.is_empty() => {
+                debug!("Read {} of {} with {}", COMIC_INFO, path.display(), program);
+                return Some(String::from_utf8_lossy(&output.stdout).into_owned());
+            }
+            Ok(_) => debug!("{} found no {} in {}", program, COMIC_INFO, path.display()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => debug!("{} is not installed", program),
+            Err(e) => debug!("Could not run {}: {}", program, e),
+        }
+    }
+    None
+}
+
+// Returns nothing for archives without ComicInfo.xml or that cannot be read, so the
+// title and chapter come from the filename instead
+pub fn read(path: &Path) -> Option<ComicInfo> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    let xml = match extension.as_deref() {
+        Some("cbz") | Some("zip") => read_zip(path).unwrap_or_else(|e| {
+            warn!("Could not open {}: {}", path.display(), e);
+            None
+        }),
+        Some("cbr") | Some("rar") | Some("cb7") | Some("7z") => read_with_extractor(path),
+        _ => None,
+    }?;
+    match parse(&xml) {
+        Ok(Some(comic_info)) => {
+            debug!("{} of {}: {:?}", COMIC_INFO, path.display(), comic_info);
+            Some(comic_info)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Could not read {} of {}: {}", COMIC_INFO, path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use zip::write::FileOptions;
+
+    fn comic_info(web: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\"?>\n<ComicInfo>\n  <Series> Berserk </Series>\n  \
+             <Volume>3</Volume>\n  <Number>12.5</Number>\n  <Web>{}</Web>\n</ComicInfo>\n",
+            web
+        )
+    }
+
+    #[test]
+    fn parses_the_fields() {
+        let info = parse(&comic_info("https://anilist.co/manga/30002"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.series.as_deref(), Some("Berserk"));
+        assert_eq!(info.volume, Some(3));
+        assert_eq!(info.number.as_deref(), Some("12.5"));
+        assert_eq!(info.anilist_id, Some(30002));
+
+        let info = parse("<ComicInfo><Series>  </Series><Volume>III</Volume></ComicInfo>")
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.series, None);
+        assert_eq!(info.volume, None);
+        assert_eq!(info.number, None);
+
+        assert!(parse("<Other><Series>Berserk</Series></Other>")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn reads_ids_from_web() {
+        for (web, anilist_id, mal_id) in &[
+            ("https://anilist.co/manga/30002", Some(30002), None),
+            ("https://anilist.co/manga/30002/Berserk/", Some(30002), None),
+            ("https://myanimelist.net/manga/2/Berserk", None, Some(2)),
+            (
+                "https://anilist.co/manga/30002 https://myanimelist.net/manga/2",
+                Some(30002),
+                Some(2),
+            ),
+            (
+                "https://myanimelist.net/manga/2,https://anilist.co/manga/30002",
+                Some(30002),
+                Some(2),
+            ),
+            // The first URL of a site wins
+            (
+                "https://anilist.co/manga/1 https://anilist.co/manga/2",
+                Some(1),
+                None,
+            ),
+            ("https://anilist.co/anime/33", None, None),
+            ("https://mangadex.org/title/2", None, None),
+            ("https://anilist.co/manga/99999999999", None, None),
+            ("not a url", None, None),
+        ] {
+            let info = parse(&comic_info(web)).unwrap().unwrap();
+            assert_eq!(info.anilist_id, *anilist_id, "{}", web);
+            assert_eq!(info.mal_id, *mal_id, "{}", web);
+        }
+    }
+
+    fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, content) in entries {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn reads_zip_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let xml = comic_info("https://myanimelist.net/manga/2");
+        for (filename, entry) in &[
+            ("root.cbz", "ComicInfo.xml"),
+            ("nested.cbz", "Berserk c012/ComicInfo.xml"),
+            ("lowercase.zip", "comicinfo.xml"),
+        ] {
+            let path = dir.path().join(filename);
+            write_zip(&path, &[("001.jpg", "jpeg"), (entry, &xml)]);
+            let info = read(&path).unwrap();
+            assert_eq!(info.series.as_deref(), Some("Berserk"), "{}", filename);
+            assert_eq!(info.mal_id, Some(2), "{}", filename);
+        }
+
+        let path = dir.path().join("untagged.cbz");
+        write_zip(&path, &[("001.jpg", "jpeg")]);
+        assert!(read(&path).is_none());
+
+        // Neither an archive nor readable, so the filename is used instead
+        let path = dir.path().join("broken.cbz");
+        fs::write(&path, "not a zip").unwrap();
+        assert!(read(&path).is_none());
+        let path = dir.path().join("Berserk c012.pdf");
+        fs::write(&path, xml).unwrap();
+        assert!(read(&path).is_none());
+    }
+}
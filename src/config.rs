@@ -0,0 +1,857 @@
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use regex::Regex;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use url::Url;
+
+use crate::anilist::{oauth, request};
+use crate::edit;
+use crate::error::MendoError;
+use crate::logging;
+use crate::output::{self, Record};
+use crate::progress::ChapterPolicy;
+use crate::tracker;
+use crate::util::MendoConfig;
+use crate::PROGRAM_NAME;
+
+// Bump this together with a new entry in `MIGRATIONS` whenever a released layout changes
+pub const CONFIG_VERSION: u64 = 2;
+
+// `MIGRATIONS[n]` turns a version `n + 1` file into a version `n + 2` one
+const MIGRATIONS: [fn(&mut Mapping); 1] = [v1_to_v2];
+
+// Lives in the `parser` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ParserConfig {
+    // Used by `update` when --regexp is not given
+    pub regexp: Option<String>,
+    pub chapter_policy: ChapterPolicy,
+    // Archives with these extensions are light novels, tracked by volume
+    pub novel_extensions: Vec<String>,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        ParserConfig {
+            regexp: None,
+            chapter_policy: ChapterPolicy::default(),
+            novel_extensions: vec!["epub".to_string()],
+        }
+    }
+}
+
+// Lives in the `notifications` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    // Notify about every update, with the undo button
+    pub enabled: bool,
+    // Notify when `update` fails
+    pub errors: bool,
+    // Milliseconds a notification stays up, which is also how long undo is offered
+    pub timeout: u32,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        NotificationsConfig {
+            enabled: true,
+            errors: true,
+            timeout: 10000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct MalConfig {
+    pub client_id: String,
+    pub token: String,
+    pub refresh_token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct KitsuConfig {
+    pub user_id: String,
+    pub token: String,
+}
+
+// Lives in the `trackers` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TrackersConfig {
+    // Every tracker listed here is updated by the `update` subcommand
+    pub enabled: Vec<String>,
+    pub mal: MalConfig,
+    pub kitsu: KitsuConfig,
+}
+
+impl Default for TrackersConfig {
+    fn default() -> Self {
+        TrackersConfig {
+            enabled: vec!["anilist".to_string()],
+            mal: MalConfig::default(),
+            kitsu: KitsuConfig::default(),
+        }
+    }
+}
+
+// Lives in the `cache` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    // Download covers into `covers` in the data directory to show them in notifications
+    pub covers: bool,
+    // Hours before user.yml is queried again, 0 keeps it until the next `mendo auth`
+    pub user_max_age: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            covers: true,
+            user_max_age: 24 * 7,
+        }
+    }
+}
+
+// Used when neither --config nor MENDO_CONFIG point somewhere else
+pub fn default_path() -> Result<PathBuf> {
+    Ok(confy::get_configuration_file_path(PROGRAM_NAME, None)?)
+}
+
+// `default-config.yml` becomes `default-config.yml.<suffix>`
+fn backup(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}", suffix));
+    let backup = PathBuf::from(backup);
+    write_atomic(&backup, &fs::read(path)?)?;
+    debug!("Backed up {} to {}", path.display(), backup.display());
+    Ok(backup)
+}
+
+// The previous file is always kept as `default-config.yml.bak`, so a bad rewrite can be undone by hand
+fn write(path: &Path, value: &Value) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if path.exists() {
+        backup(path, "bak")?;
+    }
+    write_atomic(path, serde_yaml::to_string(value)?.as_bytes())
+}
+
+// Written next to the file first, so an interrupted write never leaves half of it behind.
+// Config files are written before the lock is taken, so two mendo starting at once (and
+// both migrating the file) each write their own temporary file.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", process::id()));
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+// Settings overridden by the environment keep what the file says, they are never saved
+pub fn store(cfg: &MendoConfig) -> Result<()> {
+    let mut value = serde_yaml::to_value(cfg)?;
+    let on_disk: Option<Value> = fs::read_to_string(&cfg.path)
+        .ok()
+        .and_then(|content| serde_yaml::from_str(&content).ok());
+    let defaults = serde_yaml::to_value(MendoConfig::default())?;
+    for key in setting_keys(&value, &defaults) {
+        let env = match env_value(&key, lookup(&value, &key)) {
+            Some(env) => env,
+            None => continue,
+        };
+        if lookup(&value, &key) != Some(&env) {
+            warn!(
+                "`{}` is overridden by {}, so its new value is not saved",
+                key,
+                env_name(&key)
+            );
+        }
+        let saved = on_disk
+            .as_ref()
+            .and_then(|on_disk| lookup(on_disk, &key))
+            .or_else(|| lookup(&defaults, &key))
+            .cloned()
+            .unwrap_or(Value::Null);
+        set_key(&mut value, &key, saved);
+    }
+    write(&cfg.path, &value)
+}
+
+// Reads the file as it is on disk and migrates it from older versions of mendo
+fn read(path: &Path) -> Result<Value> {
+    let content = fs::read_to_string(path)?;
+    let mut value: Value = serde_yaml::from_str(&content)
+        .map_err(|e| MendoError::Config(format!("{} is not valid YAML: {}", path.display(), e)))?;
+    let mapping = match value.as_mapping_mut() {
+        Some(mapping) => mapping,
+        None => {
+            return Err(MendoError::Config(format!(
+                "{} should hold `key: value` pairs",
+                path.display()
+            ))
+            .into())
+        }
+    };
+    // Files written before the schema was versioned have no `version`
+    let version = match mapping.get(&Value::from("version")) {
+        None => 1,
+        Some(version) => version.as_u64().ok_or_else(|| {
+            MendoError::Config(format!("`version` in {} is not a number", path.display()))
+        })?,
+    };
+    if version > CONFIG_VERSION {
+        return Err(MendoError::Config(format!(
+            "{} is version {}, but this mendo only knows up to version {}. Update mendo",
+            path.display(),
+            version,
+            CONFIG_VERSION
+        ))
+        .into());
+    }
+    if version < CONFIG_VERSION {
+        let backup = backup(path, &format!("v{}.bak", version))?;
+        // The logger is set up from the migrated file, so this goes straight to stderr
+        eprintln!(
+            "Migrated {} from version {} to {}, the old file is kept as {}",
+            path.display(),
+            version,
+            CONFIG_VERSION,
+            backup.display()
+        );
+        for migration in &MIGRATIONS[version.max(1) as usize - 1..] {
+            migration(mapping);
+        }
+        mapping.insert(Value::from("version"), Value::from(CONFIG_VERSION));
+        write(path, &value)?;
+    }
+    Ok(value)
+}
+
+// Creates the config file on the first run, applies the MENDO_* overrides and refuses
+// to go on with values that would only fail later
+pub fn load(path: &Path, data_dir: &Path) -> Result<MendoConfig<'static>> {
+    let defaults = serde_yaml::to_value(MendoConfig::default())?;
+    let mut value = if path.exists() {
+        read(path)?
+    } else {
+        info!("Creating the default config file at {}", path.display());
+        write(path, &defaults)?;
+        defaults.clone()
+    };
+
+    let mut overrides = Vec::new();
+    for key in setting_keys(&value, &defaults) {
+        if let Some(env) = env_value(
+            &key,
+            lookup(&value, &key).or_else(|| lookup(&defaults, &key)),
+        ) {
+            debug!("`{}` is overridden by {}", key, env_name(&key));
+            set_key(&mut value, &key, env);
+            overrides.push(env_name(&key));
+        }
+    }
+    let source = if overrides.is_empty() {
+        path.display().to_string()
+    } else {
+        format!("{} with {}", path.display(), overrides.join(", "))
+    };
+
+    let mut cfg: MendoConfig = serde_yaml::from_value(value)
+        .map_err(|e| MendoError::Config(format!("{}: {}", source, e)))?;
+    validate(&cfg).map_err(|problems| {
+        MendoError::Config(format!(
+            "{} has invalid values:\n  - {}",
+            source,
+            problems.join("\n  - ")
+        ))
+    })?;
+    cfg.path = path.to_path_buf();
+    cfg.data_dir = data_dir.to_path_buf();
+    Ok(cfg)
+}
+
+fn is_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// `default_profile` of the main config file, read without loading it as a whole
+pub fn default_profile(path: &Path) -> Option<String> {
+    let value: Value = serde_yaml::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    value.get("default_profile")?.as_str().map(str::to_string)
+}
+
+// A profile keeps its config file in `profiles` next to the main one and its data in
+// `profiles` in the data directory. Its config file starts as a copy of the main one,
+// without the tokens, so other profiles are never logged out by it.
+pub fn profile_paths(path: &Path, data_dir: &Path, name: &str) -> Result<(PathBuf, PathBuf)> {
+    if !is_profile_name(name) {
+        return Err(anyhow!(
+            "`{}` is not a valid profile name, use letters, digits, `-` and `_`",
+            name
+        ));
+    }
+    let profile_path = path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join("profiles")
+        .join(format!("{}.yml", name));
+    let profile_data_dir = data_dir.join("profiles").join(name);
+    if !profile_path.exists() {
+        let mut value = if path.exists() {
+            read(path)?
+        } else {
+            serde_yaml::to_value(MendoConfig::default())?
+        };
+        let defaults = MendoConfig::default();
+        set_key(&mut value, "token", Value::from(defaults.token.as_ref()));
+        for key in &TRACKER_LOGIN_KEYS {
+            set_key(&mut value, key, Value::from(""));
+        }
+        if let Some(mapping) = value.as_mapping_mut() {
+            mapping.remove(&Value::from("default_profile"));
+        }
+        info!("Creating profile `{}` at {}", name, profile_path.display());
+        write(&profile_path, &value)?;
+    }
+    Ok((profile_path, profile_data_dir))
+}
+
+// Collects every problem at once, so the file does not have to be fixed one error at a time
+pub fn validate(cfg: &MendoConfig) -> std::result::Result<(), Vec<String>> {
+    let mut problems = Vec::new();
+    if let Err(e) = Url::parse(&cfg.url) {
+        problems.push(format!("`url` is not a valid url: {}", e));
+    }
+    if let Some(regexp) = &cfg.parser.regexp {
+        match Regex::new(regexp) {
+            Ok(re) if re.captures_len() < 2 => problems.push(
+                "`parser.regexp` needs a capture group for the title, e.g. `^(.*) c\\d+`"
+                    .to_string(),
+            ),
+            Ok(_) => (),
+            Err(e) => problems.push(format!("`parser.regexp` is not a valid regex: {}", e)),
+        }
+    }
+    if let Some(name) = cfg
+        .default_profile
+        .as_deref()
+        .filter(|name| !is_profile_name(name))
+    {
+        problems.push(format!(
+            "`default_profile` `{}` is not a valid profile name, use letters, digits, `-` and `_`",
+            name
+        ));
+    }
+    if cfg.trackers.enabled.is_empty() {
+        problems.push("`trackers.enabled` needs at least one tracker".to_string());
+    }
+    for name in &cfg.trackers.enabled {
+        if !tracker::NAMES.contains(&name.as_str()) {
+            problems.push(format!(
+                "`trackers.enabled` has unknown tracker `{}`, supported trackers are {}",
+                name,
+                tracker::NAMES.join(", ")
+            ));
+        }
+    }
+    if let Err(e) = logging::parse_level(&cfg.logging.level) {
+        problems.push(format!("`logging.level`: {}", e));
+    }
+    for (target, level) in &cfg.logging.levels {
+        if let Err(e) = logging::parse_level(level) {
+            problems.push(format!("`logging.levels.{}`: {}", target, e));
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+fn take(mapping: &mut Mapping, key: &str) -> Option<Value> {
+    mapping.remove(&Value::from(key))
+}
+
+fn section<'a>(mapping: &'a mut Mapping, key: &str) -> &'a mut Mapping {
+    let key = Value::from(key);
+    if !matches!(mapping.get(&key), Some(Value::Mapping(_))) {
+        mapping.insert(key.clone(), Value::Mapping(Mapping::new()));
+    }
+    mapping
+        .get_mut(&key)
+        .and_then(Value::as_mapping_mut)
+        .expect("Safe because it was just made a mapping")
+}
+
+// `notifications.timeout` is overridden by MENDO_NOTIFICATIONS_TIMEOUT
+fn env_name(key: &str) -> String {
+    format!("MENDO_{}", key.replace('.', "_").to_uppercase())
+}
+
+// Text settings take the variable as it is, everything else is read as YAML
+fn env_value(key: &str, current: Option<&Value>) -> Option<Value> {
+    let raw = env::var(env_name(key)).ok()?;
+    Some(match current {
+        Some(Value::String(_)) => Value::String(raw),
+        _ => serde_yaml::from_str(&raw).unwrap_or(Value::String(raw)),
+    })
+}
+
+// Every setting in `value` or in the defaults, as dotted keys
+fn setting_keys(value: &Value, defaults: &Value) -> Vec<String> {
+    fn collect(value: &Value, prefix: &str, keys: &mut Vec<String>) {
+        match value {
+            Value::Mapping(mapping) => {
+                for (key, value) in mapping {
+                    if let Some(key) = key.as_str() {
+                        let key = if prefix.is_empty() {
+                            key.to_string()
+                        } else {
+                            format!("{}.{}", prefix, key)
+                        };
+                        collect(value, &key, keys);
+                    }
+                }
+            }
+            _ if prefix.is_empty() || prefix == "version" => (),
+            _ => keys.push(prefix.to_string()),
+        }
+    }
+    let mut keys = Vec::new();
+    collect(defaults, "", &mut keys);
+    collect(value, "", &mut keys);
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+// Creates the sections on the way, an existing key keeps its place in the file
+fn set_key(root: &mut Value, key: &str, new: Value) {
+    let (section_path, name) = split_key(key);
+    let mut mapping = match root.as_mapping_mut() {
+        Some(mapping) => mapping,
+        None => return,
+    };
+    for part in section_path.split('.').filter(|part| !part.is_empty()) {
+        mapping = section(mapping, part);
+    }
+    match mapping.get_mut(&Value::from(name)) {
+        Some(value) => *value = new,
+        None => {
+            mapping.insert(Value::from(name), new);
+        }
+    }
+}
+
+// Version 1 was flat, version 2 groups the settings into sections
+fn v1_to_v2(cfg: &mut Mapping) {
+    if let Some(chapter_policy) = take(cfg, "chapter_policy") {
+        section(cfg, "parser").insert(Value::from("chapter_policy"), chapter_policy);
+    }
+    // `trackers` used to be the list of enabled trackers
+    if matches!(cfg.get(&Value::from("trackers")), Some(Value::Sequence(_))) {
+        let enabled = take(cfg, "trackers").expect("Safe because it was just checked");
+        section(cfg, "trackers").insert(Value::from("enabled"), enabled);
+    }
+    for (old, tracker, new) in &[
+        ("mal_client_id", "mal", "client_id"),
+        ("mal_token", "mal", "token"),
+        ("mal_refresh_token", "mal", "refresh_token"),
+        ("kitsu_user_id", "kitsu", "user_id"),
+        ("kitsu_token", "kitsu", "token"),
+    ] {
+        if let Some(value) = take(cfg, old) {
+            section(section(cfg, "trackers"), tracker).insert(Value::from(*new), value);
+        }
+    }
+}
+
+// Keys whose values `show` never prints
+const SECRET_KEYS: [&str; 5] = [
+    "secret",
+    "token",
+    "trackers.mal.token",
+    "trackers.mal.refresh_token",
+    "trackers.kitsu.token",
+];
+
+// Tracker logins, which a new profile does not inherit from the main config file
+const TRACKER_LOGIN_KEYS: [&str; 4] = [
+    "trackers.mal.token",
+    "trackers.mal.refresh_token",
+    "trackers.kitsu.token",
+    "trackers.kitsu.user_id",
+];
+
+fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.')
+        .try_fold(value, |value, part| value.get(part))
+}
+
+// Splits `a.b.c` into the `a.b` section and `c`
+fn split_key(key: &str) -> (&str, &str) {
+    match key.rfind('.') {
+        Some(i) => (&key[..i], &key[i + 1..]),
+        None => ("", key),
+    }
+}
+
+fn section_mut<'a>(root: &'a mut Value, section: &str) -> Option<&'a mut Mapping> {
+    if section.is_empty() {
+        return root.as_mapping_mut();
+    }
+    section
+        .split('.')
+        .try_fold(root, |value, part| value.get_mut(part))
+        .and_then(Value::as_mapping_mut)
+}
+
+fn print_value(value: &Value) -> Result<()> {
+    if output::is_json() {
+        println!("{}", serde_json::to_string(value)?);
+        return Ok(());
+    }
+    match value {
+        Value::String(s) => println!("{}", s),
+        Value::Mapping(_) | Value::Sequence(_) => {
+            print!(
+                "{}",
+                serde_yaml::to_string(value)?.trim_start_matches("---\n")
+            )
+        }
+        _ => println!(
+            "{}",
+            serde_yaml::to_string(value)?
+                .trim_start_matches("---\n")
+                .trim_end()
+        ),
+    }
+    Ok(())
+}
+
+pub fn print_path(path: &Path) -> Result<()> {
+    output::emit(
+        &path.display().to_string(),
+        Record {
+            event: "config_path",
+            message: Some(path.display().to_string()),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+pub fn show(cfg: &MendoConfig) -> Result<()> {
+    let mut value = serde_yaml::to_value(cfg)?;
+    for key in &SECRET_KEYS {
+        let is_set = match lookup(&value, key).and_then(Value::as_str) {
+            Some(secret) => !secret.is_empty() && (*key != "token" || cfg.access_token_is_valid()),
+            None => false,
+        };
+        let (section, name) = split_key(key);
+        if let Some(secret) = section_mut(&mut value, section)
+            .filter(|_| is_set)
+            .and_then(|parent| parent.get_mut(&Value::from(name)))
+        {
+            *secret = Value::from("[REDACTED]");
+        }
+    }
+    print_value(&value)
+}
+
+pub fn get(cfg: &MendoConfig, key: &str) -> Result<()> {
+    let value = serde_yaml::to_value(cfg)?;
+    match lookup(&value, key) {
+        Some(value) => print_value(value),
+        None => Err(anyhow!(
+            "`{}` is not a setting, `mendo config show` lists them all",
+            key
+        )),
+    }
+}
+
+// `value` is read as YAML, so `true`, `60` or `[anilist, mal]` keep their type
+pub fn set(cfg: &mut MendoConfig, key: &str, value: &str) -> Result<()> {
+    if key == "version" {
+        return Err(anyhow!("`version` is managed by mendo itself"));
+    }
+    let unknown = || {
+        anyhow!(
+            "`{}` is not a setting, `mendo config show` lists them all",
+            key
+        )
+    };
+    let parsed: Value =
+        serde_yaml::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    // Retried as a plain string, so e.g. a numeric Kitsu user id still fits a text setting
+    let mut new_cfg = None;
+    for new in &[parsed, Value::String(value.to_string())] {
+        let mut root = serde_yaml::to_value(&*cfg)?;
+        set_key(&mut root, key, new.clone());
+        if let Ok(candidate) = serde_yaml::from_value::<MendoConfig>(root) {
+            // Keys serde does not know about are dropped, so they do not come back out
+            if lookup(&serde_yaml::to_value(&candidate)?, key) != Some(new) {
+                return Err(unknown());
+            }
+            new_cfg = Some(candidate);
+            break;
+        }
+    }
+    let mut new_cfg =
+        new_cfg.ok_or_else(|| anyhow!("`{}` is not a valid value for `{}`", value, key))?;
+    new_cfg.path = cfg.path.clone();
+    new_cfg.data_dir = cfg.data_dir.clone();
+    validate(&new_cfg).map_err(|problems| anyhow!("{}", problems.join(", ")))?;
+    *cfg = new_cfg;
+    store(cfg)?;
+    output::emit(
+        &format!("`{}` is set to `{}`.", key, value),
+        Record {
+            event: "config_set",
+            key: Some(key.to_string()),
+            message: Some(value.to_string()),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+// Works on the file itself, so it also fixes a config file that no longer loads
+pub fn edit(path: &Path, data_dir: &Path) -> Result<()> {
+    if path.exists() {
+        backup(path, "bak")?;
+    } else {
+        write(path, &serde_yaml::to_value(MendoConfig::default())?)?;
+    }
+    let mut command = edit::editor_command();
+    debug!("Opening {} with {:?}", path.display(), command);
+    let status = command.arg(path).status()?;
+    if !status.success() {
+        return Err(anyhow!("The editor exited with {}", status));
+    }
+    // Reports what is wrong right away instead of on the next update
+    load(path, data_dir)?;
+    output::emit(
+        &format!("{} is valid.", path.display()),
+        Record {
+            event: "config_saved",
+            message: Some(path.display().to_string()),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+// The file was already validated when it was loaded, this also asks Anilist
+pub fn check(cfg: &MendoConfig, client: &Client) -> Result<()> {
+    let mut failed = 0;
+    let mut report = |key: &str, ok: bool, text: String| {
+        if !ok {
+            failed += 1;
+        }
+        output::emit(
+            &format!("[{}] {}", if ok { "ok" } else { "FAILED" }, text),
+            Record {
+                event: "check",
+                key: Some(key.to_string()),
+                ok: Some(ok),
+                message: Some(text),
+                ..Record::default()
+            },
+        );
+    };
+
+    report("file", true, format!("{} is valid", cfg.path.display()));
+
+    let callback_ok = match Url::parse(&cfg.url) {
+        Ok(url) => {
+            url.scheme() == "http"
+                && matches!(url.host_str(), Some("localhost") | Some("127.0.0.1"))
+                && url.port() == Some(8080)
+        }
+        Err(_) => false,
+    };
+    report(
+        "url",
+        callback_ok,
+        if callback_ok {
+            format!("`{}` reaches mendo during `mendo auth`", cfg.url)
+        } else {
+            format!(
+                "`{}` does not reach mendo, which listens on http://{} during `mendo auth`",
+                cfg.url,
+                oauth::CALLBACK_ADDR
+            )
+        },
+    );
+
+    // A check that cannot reach Anilist fails on its own, the others still run
+    match oauth::check_client(cfg, client) {
+        Ok(true) => report(
+            "id",
+            true,
+            format!("Anilist accepts the client `{}` and its secret", cfg.id),
+        ),
+        Ok(false) => report(
+            "id",
+            false,
+            format!(
+                "Anilist does not accept the client `{}` with that secret, recheck `id` and `secret`",
+                cfg.id
+            ),
+        ),
+        Err(e) => report("id", false, format!("Could not check the client: {}", e)),
+    }
+
+    if cfg.access_token_is_valid() {
+        match request::check_token(cfg, client) {
+            Ok(Some(name)) => report(
+                "token",
+                true,
+                format!("The Anilist token belongs to `{}`", name),
+            ),
+            Ok(None) => report(
+                "token",
+                false,
+                "Anilist refused the token, run `mendo auth --force`".to_string(),
+            ),
+            Err(e) => report("token", false, format!("Could not check the token: {}", e)),
+        }
+    } else {
+        report(
+            "token",
+            false,
+            "There is no Anilist token yet, run `mendo auth`".to_string(),
+        );
+    }
+
+    match failed {
+        0 => Ok(()),
+        failed => Err(anyhow!("{} of the checks failed", failed)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::{DecimalPolicy, RangePolicy};
+
+    const V1: &str = "\
+id: 1234
+secret: my-client-secret
+name: mendo
+url: http://localhost:8080/callback
+token: my-anilist-token
+chapter_policy:
+  decimal: skip
+  range: lower
+trackers:
+  - anilist
+  - mal
+mal_client_id: my-mal-client
+mal_token: my-mal-token
+mal_refresh_token: my-mal-refresh
+kitsu_user_id: \"42\"
+kitsu_token: my-kitsu-token
+";
+
+    #[test]
+    fn migrates_a_flat_v1_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("default-config.yml");
+        fs::write(&path, V1).unwrap();
+
+        let cfg = load(&path, dir.path()).unwrap();
+
+        // The file as it was is kept next to the migrated one
+        let backup = dir.path().join("default-config.yml.v1.bak");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), V1);
+        let on_disk: Value = serde_yaml::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            lookup(&on_disk, "version"),
+            Some(&Value::from(CONFIG_VERSION))
+        );
+        assert_eq!(lookup(&on_disk, "mal_token"), None);
+
+        assert_eq!(cfg.version, CONFIG_VERSION);
+        assert_eq!(cfg.id, 1234);
+        assert_eq!(cfg.secret, "my-client-secret");
+        assert_eq!(cfg.token, "my-anilist-token");
+        assert_eq!(cfg.parser.chapter_policy.decimal, DecimalPolicy::Skip);
+        assert_eq!(cfg.parser.chapter_policy.range, RangePolicy::Lower);
+        assert_eq!(cfg.trackers.enabled, vec!["anilist", "mal"]);
+        assert_eq!(cfg.trackers.mal.client_id, "my-mal-client");
+        assert_eq!(cfg.trackers.mal.token, "my-mal-token");
+        assert_eq!(cfg.trackers.mal.refresh_token, "my-mal-refresh");
+        assert_eq!(cfg.trackers.kitsu.user_id, "42");
+        assert_eq!(cfg.trackers.kitsu.token, "my-kitsu-token");
+
+        // Sections version 1 did not have take their defaults
+        let defaults = MendoConfig::default();
+        assert_eq!(cfg.parser.regexp, None);
+        assert_eq!(
+            cfg.parser.novel_extensions,
+            defaults.parser.novel_extensions
+        );
+        assert_eq!(cfg.notifications.enabled, defaults.notifications.enabled);
+        assert_eq!(cfg.notifications.timeout, defaults.notifications.timeout);
+        assert_eq!(cfg.cache.covers, defaults.cache.covers);
+        assert_eq!(cfg.cache.user_max_age, defaults.cache.user_max_age);
+        assert_eq!(cfg.lock_timeout, defaults.lock_timeout);
+    }
+
+    #[test]
+    fn profiles_start_without_logins() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("default-config.yml");
+        fs::write(&path, V1).unwrap();
+        load(&path, dir.path()).unwrap();
+
+        let (profile_path, profile_data_dir) = profile_paths(&path, dir.path(), "work").unwrap();
+        assert_eq!(profile_data_dir, dir.path().join("profiles").join("work"));
+        let profile = load(&profile_path, dir.path()).unwrap();
+
+        // The client settings are shared, every login is left to the profile
+        assert_eq!(profile.id, 1234);
+        assert_eq!(profile.secret, "my-client-secret");
+        assert_eq!(profile.trackers.mal.client_id, "my-mal-client");
+        assert_eq!(profile.token, MendoConfig::default().token);
+        assert_eq!(profile.trackers.mal.token, "");
+        assert_eq!(profile.trackers.mal.refresh_token, "");
+        assert_eq!(profile.trackers.kitsu.token, "");
+        assert_eq!(profile.trackers.kitsu.user_id, "");
+
+        assert!(profile_paths(&path, dir.path(), "../work").is_err());
+    }
+
+    #[test]
+    fn leaves_a_current_file_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("default-config.yml");
+        let content = serde_yaml::to_string(&MendoConfig::default()).unwrap();
+        fs::write(&path, &content).unwrap();
+
+        load(&path, dir.path()).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        assert!(!dir.path().join("default-config.yml.v1.bak").exists());
+        assert!(!dir.path().join("default-config.yml.bak").exists());
+    }
+}
@@ -0,0 +1,240 @@
+use anyhow::{anyhow, Result};
+use log::{debug, error};
+use reqwest::blocking::Client;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use crate::anilist::model::{MediaList, ScoreFormat};
+use crate::anilist::request;
+use crate::error::MendoError;
+use crate::output::{self, Record};
+use crate::util::{self, MendoConfig};
+
+#[cfg(target_family = "windows")]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(target_family = "windows"))]
+const DEFAULT_EDITOR: &str = "vi";
+
+// Anilist interprets the score in the format chosen by the user, so it is only validated here.
+// A score of 0 removes the score from the entry.
+pub fn parse_score(input: &str, format: ScoreFormat) -> Result<f64> {
+    let input = input.trim();
+    let (score, valid) = match format {
+        ScoreFormat::Point3 => {
+            let score = match input {
+                ":(" => 1.0,
+                ":|" => 2.0,
+                ":)" => 3.0,
+                _ => input.parse::<f64>()?,
+            };
+            (score, score.fract() == 0.0 && (0.0..=3.0).contains(&score))
+        }
+        ScoreFormat::Point5 => {
+            let score = input.parse::<f64>()?;
+            (score, score.fract() == 0.0 && (0.0..=5.0).contains(&score))
+        }
+        ScoreFormat::Point10 => {
+            let score = input.parse::<f64>()?;
+            (score, score.fract() == 0.0 && (0.0..=10.0).contains(&score))
+        }
+        ScoreFormat::Point10Decimal => {
+            let score = (input.parse::<f64>()? * 10.0).round() / 10.0;
+            (score, (0.0..=10.0).contains(&score))
+        }
+        ScoreFormat::Point100 => {
+            let score = input.parse::<f64>()?;
+            (
+                score,
+                score.fract() == 0.0 && (0.0..=100.0).contains(&score),
+            )
+        }
+    };
+
+    if valid {
+        Ok(score)
+    } else {
+        error!("Score `{}` is not valid for {:?}", input, format);
+        Err(anyhow!(
+            "Score `{}` is not valid for your score format ({})",
+            input,
+            score_format_hint(format)
+        ))
+    }
+}
+
+fn score_format_hint(format: ScoreFormat) -> &'static str {
+    match format {
+        ScoreFormat::Point100 => "a whole number from 0 to 100",
+        ScoreFormat::Point10Decimal => "a number from 0 to 10 with one decimal",
+        ScoreFormat::Point10 => "a whole number from 0 to 10",
+        ScoreFormat::Point5 => "a whole number of stars from 0 to 5",
+        ScoreFormat::Point3 => "1, 2, 3 or :( :| :)",
+    }
+}
+
+fn get_entry(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    title: &str,
+    client: &Client,
+) -> Result<(i32, MediaList)> {
+    let user_id = util::get_user_id(cfg, data_dir, client)?;
+    let media_id = util::get_media_id_by_name(cfg, data_dir, title, None, client)?;
+    // Only looked up, editing should neither create entries nor miss dropped ones
+    let entry = util::find_media_list(cfg, user_id, media_id, None, client)?
+        .ok_or(MendoError::NoListEntry(media_id))?;
+    Ok((media_id, entry))
+}
+
+pub fn rate(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    title: &str,
+    score: &str,
+    client: &Client,
+) -> Result<()> {
+    let user = util::get_user(cfg, data_dir, client)?;
+    let format = user
+        .media_list_options
+        .map_or(ScoreFormat::Point10, |options| options.score_format);
+    let score = parse_score(score, format)?;
+    let (media_id, entry) = get_entry(cfg, data_dir, title, client)?;
+    request::update_score(cfg, entry.entry_id, score, client)?;
+    output::emit(
+        &format!("`{}` is now rated {} ({:?})", title, score, format),
+        Record {
+            event: "rated",
+            media_id: Some(media_id),
+            title: Some(title.to_string()),
+            score: Some(score),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+// Same convention as git: $VISUAL, then $EDITOR, then a platform default
+pub fn editor_command() -> Command {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let mut parts = editor.split_whitespace();
+    let mut command = Command::new(parts.next().unwrap_or(DEFAULT_EDITOR));
+    command.args(parts);
+    command
+}
+
+pub fn edit_notes(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    title: &str,
+    client: &Client,
+) -> Result<()> {
+    let (media_id, entry) = get_entry(cfg, data_dir, title, client)?;
+    let old_notes = entry.notes.unwrap_or_default();
+    // Created new and only readable by the user, it is removed again when dropped,
+    // also when the editor cannot be started
+    let mut notes_file = tempfile::Builder::new()
+        .prefix("mendo-notes-")
+        .suffix(".txt")
+        .tempfile()?;
+    notes_file.write_all(old_notes.as_bytes())?;
+    notes_file.flush()?;
+
+    let mut command = editor_command();
+    debug!("Opening notes of `{}` with {:?}", title, command);
+    let status = command.arg(notes_file.path()).status()?;
+    // Editors may replace the file rather than write to it, so it is read again by path
+    let new_notes = fs::read_to_string(notes_file.path())?;
+    notes_file.close()?;
+    if !status.success() {
+        error!("The editor exited with {}", status);
+        return Err(anyhow!(
+            "The editor exited with {}, notes are not changed",
+            status
+        ));
+    }
+
+    let new_notes = new_notes.trim_end();
+    if new_notes == old_notes.trim_end() {
+        output::emit(
+            &format!("Notes of `{}` are unchanged.", title),
+            Record {
+                event: "notes_unchanged",
+                media_id: Some(media_id),
+                title: Some(title.to_string()),
+                ..Record::default()
+            },
+        );
+        return Ok(());
+    }
+    request::update_notes(cfg, entry.entry_id, new_notes, client)?;
+    output::emit(
+        &format!("Notes of `{}` are saved.", title),
+        Record {
+            event: "notes_saved",
+            media_id: Some(media_id),
+            title: Some(title.to_string()),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+// `value` of None flips the current state
+pub fn set_private(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    title: &str,
+    value: Option<bool>,
+    client: &Client,
+) -> Result<()> {
+    let (media_id, entry) = get_entry(cfg, data_dir, title, client)?;
+    let private = value.unwrap_or_else(|| !entry.private.unwrap_or(false));
+    request::update_visibility(cfg, entry.entry_id, Some(private), None, client)?;
+    output::emit(
+        &format!(
+            "`{}` is now {}.",
+            title,
+            if private { "private" } else { "public" }
+        ),
+        Record {
+            event: "visibility",
+            media_id: Some(media_id),
+            title: Some(title.to_string()),
+            private: Some(private),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+pub fn set_hidden(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    title: &str,
+    value: Option<bool>,
+    client: &Client,
+) -> Result<()> {
+    let (media_id, entry) = get_entry(cfg, data_dir, title, client)?;
+    let hidden = value.unwrap_or_else(|| !entry.hidden_from_status_lists.unwrap_or(false));
+    request::update_visibility(cfg, entry.entry_id, None, Some(hidden), client)?;
+    output::emit(
+        &format!(
+            "`{}` is now {} status lists.",
+            title,
+            if hidden { "hidden from" } else { "shown in" }
+        ),
+        Record {
+            event: "visibility",
+            media_id: Some(media_id),
+            title: Some(title.to_string()),
+            hidden: Some(hidden),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
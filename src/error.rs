@@ -0,0 +1,58 @@
+use thiserror::Error;
+
+use crate::anilist::model::QueryError;
+
+pub const NETWORK_EXIT_CODE: i32 = 8;
+
+// Errors the update flow and the CLI act on. Everything else stays an `anyhow` error.
+#[derive(Error, Debug)]
+pub enum MendoError {
+    #[error("Unauthorized! Run `mendo auth` to authorize again")]
+    Unauthorized,
+    // Any tracker but Anilist, which is authorized with plain `mendo auth`
+    #[error("{0} is not authorized. Run `mendo auth --tracker {0}` first")]
+    TrackerUnauthorized(&'static str),
+    #[error("Still rate limited by Anilist after {0} tries")]
+    RateLimited(u8),
+    #[error("Could not find `{0}` on Anilist. Maybe recheck your archive filename?")]
+    NotFound(String),
+    #[error("Media `{0}` is not on your list")]
+    NoListEntry(i32),
+    #[error("Could not parse {0}")]
+    Parse(String),
+    #[error("Could not reach the server: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("Anilist returned errors: {}", format_query_errors(.0))]
+    GraphQL(Vec<QueryError>),
+    #[error("Invalid config file: {0}")]
+    Config(String),
+}
+
+impl MendoError {
+    // 1 is left for every other error and for invalid arguments, which clap exits with
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MendoError::Unauthorized | MendoError::TrackerUnauthorized(_) => 3,
+            MendoError::RateLimited(_) => 4,
+            MendoError::NotFound(_) => 5,
+            MendoError::NoListEntry(_) => 6,
+            MendoError::Parse(_) => 7,
+            MendoError::Network(_) => NETWORK_EXIT_CODE,
+            MendoError::GraphQL(_) => 9,
+            MendoError::Config(_) => 10,
+        }
+    }
+}
+
+fn format_query_errors(errors: &[QueryError]) -> String {
+    errors
+        .iter()
+        .map(|e| match (e.status, &e.message) {
+            (Some(status), Some(message)) => format!("{} ({})", message, status),
+            (None, Some(message)) => message.clone(),
+            (Some(status), None) => format!("status {}", status),
+            (None, None) => "unknown error".to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
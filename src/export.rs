@@ -0,0 +1,194 @@
+use anyhow::Result;
+use log::{info, warn};
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::fmt::Write as FmtWrite;
+use std::io::Write;
+use std::path::Path;
+
+use crate::anilist::model::{FuzzyDate, Media, MediaList, MediaListStatus, MediaType, User};
+use crate::anilist::request;
+use crate::error::MendoError;
+use crate::util::{self, MendoConfig};
+
+// Flat row used by the csv format, the same columns are read back by `mendo import`
+#[derive(Serialize, Debug)]
+struct CsvRow<'a> {
+    title: &'a str,
+    progress: i32,
+    status: &'a MediaListStatus,
+    progress_volumes: i32,
+    score: f64,
+    media_id: i32,
+    id_mal: Option<i32>,
+}
+
+pub fn media_title(media: &Media) -> &str {
+    media
+        .title
+        .romaji
+        .as_deref()
+        .or(media.title.english.as_deref())
+        .unwrap_or(&media.title.native)
+}
+
+// Custom lists repeat entries of the status lists, so only the latter are kept
+pub fn fetch_entries(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    client: &Client,
+) -> Result<(User, Vec<MediaList>)> {
+    let user = util::get_user(cfg, data_dir, client)?;
+    let query_result =
+        request::query_media_list_collection(cfg, user.id, MediaType::Manga, client)?;
+    let entries = match query_result.data {
+        Some(resp) => resp
+            .media_list_collection
+            .lists
+            .into_iter()
+            .filter(|list| !list.is_custom_list)
+            .flat_map(|list| list.entries)
+            .collect(),
+        None => return Err(MendoError::NotFound(format!("the list of user `{}`", user.id)).into()),
+    };
+    Ok((user, entries))
+}
+
+pub fn export(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    format: &str,
+    out: &mut dyn Write,
+    client: &Client,
+) -> Result<usize> {
+    let (user, entries) = fetch_entries(cfg, data_dir, client)?;
+    info!("Exporting {} entries as {}", entries.len(), format);
+    match format {
+        "mal-xml" => write!(out, "{}", to_mal_xml(&user, &entries))?,
+        "json" => serde_json::to_writer_pretty(&mut *out, &entries)?,
+        "csv" => write_csv(&entries, out)?,
+        _ => xkcd_unreachable::xkcd_unreachable!(),
+    }
+    out.flush()?;
+    Ok(entries.len())
+}
+
+fn write_csv(entries: &[MediaList], out: &mut dyn Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(out);
+    for entry in entries {
+        let media = match &entry.media {
+            Some(media) => media,
+            None => continue,
+        };
+        writer.serialize(CsvRow {
+            title: media_title(media),
+            progress: entry.progress,
+            status: &entry.status,
+            progress_volumes: entry.progress_volumes.unwrap_or(0),
+            score: entry.score.unwrap_or(0.0),
+            media_id: media.media_id,
+            id_mal: media.id_mal,
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn mal_status(status: &MediaListStatus) -> &'static str {
+    match status {
+        MediaListStatus::Current | MediaListStatus::Repeating => "Reading",
+        MediaListStatus::Completed => "Completed",
+        MediaListStatus::Paused => "On-Hold",
+        MediaListStatus::Dropped => "Dropped",
+        MediaListStatus::Planning => "Plan to Read",
+    }
+}
+
+fn mal_date(date: &Option<FuzzyDate>) -> String {
+    match date {
+        Some(date) => format!(
+            "{:04}-{:02}-{:02}",
+            date.year.unwrap_or(0),
+            date.month.unwrap_or(0),
+            date.day.unwrap_or(0)
+        ),
+        None => "0000-00-00".to_string(),
+    }
+}
+
+fn cdata(s: &str) -> String {
+    format!("<![CDATA[{}]]>", s.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+// Follows the layout of MyAnimeList's own export so it can be imported back as is
+pub fn to_mal_xml(user: &User, entries: &[MediaList]) -> String {
+    let mut xml = String::new();
+    let mut skipped = 0;
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<myanimelist>\n");
+    xml.push_str("  <myinfo>\n");
+    let _ = writeln!(xml, "    <user_id>{}</user_id>", user.id);
+    let _ = writeln!(xml, "    <user_name>{}</user_name>", cdata(&user.name));
+    xml.push_str("    <user_export_type>2</user_export_type>\n");
+    xml.push_str("  </myinfo>\n");
+
+    for entry in entries {
+        let media = match &entry.media {
+            Some(media) => media,
+            None => continue,
+        };
+        let id_mal = match media.id_mal {
+            Some(id_mal) => id_mal,
+            None => {
+                warn!(
+                    "`{}` has no MyAnimeList id and can not be imported there, skipped",
+                    media_title(media)
+                );
+                skipped += 1;
+                continue;
+            }
+        };
+        let rereading = matches!(entry.status, MediaListStatus::Repeating);
+        xml.push_str("  <manga>\n");
+        let fields = [
+            ("manga_mangadb_id", id_mal.to_string()),
+            ("manga_title", cdata(media_title(media))),
+            ("manga_volumes", media.volumes.unwrap_or(0).to_string()),
+            ("manga_chapters", media.chapters.unwrap_or(0).to_string()),
+            ("my_id", "0".to_string()),
+            (
+                "my_read_volumes",
+                entry.progress_volumes.unwrap_or(0).to_string(),
+            ),
+            ("my_read_chapters", entry.progress.to_string()),
+            ("my_start_date", mal_date(&entry.started_at)),
+            ("my_finish_date", mal_date(&entry.completed_at)),
+            ("my_scanalation_group", cdata("")),
+            ("my_score", entry.score.unwrap_or(0.0).round().to_string()),
+            ("my_storage", String::new()),
+            ("my_retail_volumes", "0".to_string()),
+            ("my_status", mal_status(&entry.status).to_string()),
+            ("my_comments", cdata(entry.notes.as_deref().unwrap_or(""))),
+            ("my_times_read", entry.repeat.unwrap_or(0).to_string()),
+            ("my_tags", cdata("")),
+            ("my_priority", "Low".to_string()),
+            ("my_reread_value", String::new()),
+            (
+                "my_rereading",
+                if rereading { "YES" } else { "NO" }.to_string(),
+            ),
+            ("my_discuss", "YES".to_string()),
+            ("my_sns", "default".to_string()),
+            ("update_on_import", "1".to_string()),
+        ];
+        for (tag, value) in fields.iter() {
+            let _ = writeln!(xml, "    <{tag}>{value}</{tag}>", tag = tag, value = value);
+        }
+        xml.push_str("  </manga>\n");
+    }
+    xml.push_str("</myanimelist>\n");
+
+    if skipped > 0 {
+        warn!("Skipped {} entries without a MyAnimeList id", skipped);
+    }
+    xml
+}
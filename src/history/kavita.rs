@@ -0,0 +1,157 @@
+use anyhow::Result;
+use log::{debug, info};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::ReadProgress;
+use crate::progress::parse_chapter;
+use crate::util;
+use crate::PROGRAM_NAME;
+
+const PAGE_SIZE: u32 = 100;
+
+#[derive(Deserialize, Debug)]
+struct PluginToken {
+    token: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Series {
+    id: i32,
+    name: String,
+    pages_read: i32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Chapter {
+    // Kavita stores chapter numbers as strings, e.g. "12" or "12.5"
+    number: String,
+    pages: i32,
+    pages_read: i32,
+}
+
+#[derive(Deserialize, Debug)]
+struct Volume {
+    chapters: Vec<Chapter>,
+}
+
+fn authenticate(base_url: &str, api_key: &str, client: &Client) -> Result<String> {
+    let res = client
+        .post(format!("{}/api/Plugin/authenticate", base_url))
+        .query(&[("apiKey", api_key), ("pluginName", PROGRAM_NAME)])
+        .send()?;
+    let token: PluginToken = util::check_status(res, "Kavita", None)?.json()?;
+    debug!("Authenticated against Kavita");
+    Ok(token.token)
+}
+
+pub fn read_progress(base_url: &str, api_key: &str, client: &Client) -> Result<Vec<ReadProgress>> {
+    let base_url = base_url.trim_end_matches('/');
+    let token = authenticate(base_url, api_key, client)?;
+    let mut progress = Vec::new();
+    let mut page = 1;
+
+    loop {
+        info!("Fetching page {} of series from Kavita...", page);
+        let res = client
+            .post(format!("{}/api/Series/all", base_url))
+            .bearer_auth(&token)
+            .query(&[("pageNumber", page), ("pageSize", PAGE_SIZE)])
+            .json(&json!({}))
+            .send()?;
+        let series: Vec<Series> = util::check_status(res, "Kavita", None)?.json()?;
+        let count = series.len();
+
+        for s in series.into_iter().filter(|s| s.pages_read > 0) {
+            let res = client
+                .get(format!("{}/api/Series/volumes", base_url))
+                .bearer_auth(&token)
+                .query(&[("seriesId", s.id)])
+                .send()?;
+            let volumes: Vec<Volume> = util::check_status(res, "Kavita", None)?.json()?;
+            let read = volumes
+                .iter()
+                .flat_map(|v| v.chapters.iter())
+                .filter(|c| c.pages > 0 && c.pages_read >= c.pages);
+            for chapter in read {
+                match parse_chapter(&chapter.number, "") {
+                    Some(number) => progress.push(ReadProgress {
+                        title: s.name.clone(),
+                        media_id: None,
+                        chapter: number,
+                    }),
+                    None => debug!(
+                        "Could not read chapter `{}` of `{}`, skipped",
+                        chapter.number, s.name
+                    ),
+                }
+            }
+        }
+
+        if count < PAGE_SIZE as usize {
+            break;
+        }
+        page += 1;
+    }
+
+    Ok(progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, Matcher};
+
+    #[test]
+    fn reads_fully_read_chapters() {
+        let _auth = mock("POST", "/api/Plugin/authenticate")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("apiKey".into(), "key".into()),
+                Matcher::UrlEncoded("pluginName".into(), PROGRAM_NAME.into()),
+            ]))
+            .with_body(r#"{"token": "jwt", "username": "reader"}"#)
+            .create();
+        let _series = mock("POST", "/api/Series/all")
+            .match_header("authorization", "Bearer jwt")
+            .match_query(Matcher::UrlEncoded("pageNumber".into(), "1".into()))
+            .with_body(
+                r#"[
+                    {"id": 1, "name": "Berserk", "pagesRead": 80},
+                    {"id": 2, "name": "Unread", "pagesRead": 0}
+                ]"#,
+            )
+            .create();
+        let _volumes = mock("GET", "/api/Series/volumes")
+            .match_query(Matcher::UrlEncoded("seriesId".into(), "1".into()))
+            .with_body(
+                r#"[{"chapters": [
+                    {"number": "1", "pages": 20, "pagesRead": 20},
+                    {"number": "2.5", "pages": 20, "pagesRead": 20},
+                    {"number": "3", "pages": 20, "pagesRead": 5},
+                    {"number": "0", "pages": 0, "pagesRead": 0}
+                ]}]"#,
+            )
+            .create();
+
+        let base_url = format!("{}/", mockito::server_url());
+        let progress = read_progress(&base_url, "key", &Client::new()).unwrap();
+        assert_eq!(progress.len(), 2);
+        assert_eq!(progress[0].title, "Berserk");
+        assert_eq!(progress[0].media_id, None);
+        assert_eq!(progress[0].chapter, crate::progress::Chapter::Number(1));
+        // Decimals are left to the chapter policy
+        assert_eq!(progress[1].chapter, crate::progress::Chapter::Decimal(2.5));
+    }
+
+    #[test]
+    fn fails_on_wrong_api_key() {
+        let _auth = mock("POST", "/api/Plugin/authenticate")
+            .match_query(Matcher::UrlEncoded("apiKey".into(), "wrong".into()))
+            .with_status(401)
+            .create();
+        assert!(read_progress(&mockito::server_url(), "wrong", &Client::new()).is_err());
+    }
+}
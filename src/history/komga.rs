@@ -0,0 +1,132 @@
+use anyhow::Result;
+use log::{debug, info};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+use super::ReadProgress;
+use crate::progress::Chapter;
+use crate::util;
+
+const PAGE_SIZE: u32 = 500;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BookMetadata {
+    number_sort: f32,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Book {
+    series_id: String,
+    series_title: String,
+    metadata: BookMetadata,
+}
+
+#[derive(Deserialize, Debug)]
+struct BookPage {
+    content: Vec<Book>,
+    last: bool,
+}
+
+pub fn read_progress(
+    base_url: &str,
+    user: &str,
+    password: &str,
+    client: &Client,
+) -> Result<Vec<ReadProgress>> {
+    let url = format!("{}/api/v1/books", base_url.trim_end_matches('/'));
+    let mut progress = Vec::new();
+    let mut page = 0;
+
+    loop {
+        info!("Fetching page {} of read books from Komga...", page);
+        let res = client
+            .get(&url)
+            .basic_auth(user, Some(password))
+            .query(&[
+                ("read_status", "READ".to_string()),
+                ("page", page.to_string()),
+                ("size", PAGE_SIZE.to_string()),
+            ])
+            .send()?;
+        let books: BookPage = util::check_status(res, "Komga", None)?.json()?;
+        debug!("Komga returned {} books", books.content.len());
+
+        for book in books.content {
+            debug!(
+                "Book {} of series `{}` ({}) is read",
+                book.metadata.number_sort, book.series_title, book.series_id
+            );
+            progress.push(ReadProgress {
+                title: book.series_title,
+                media_id: None,
+                chapter: Chapter::from_number(book.metadata.number_sort.into()),
+            });
+        }
+
+        if books.last {
+            break;
+        }
+        page += 1;
+    }
+
+    Ok(progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, Matcher};
+
+    fn book(series_title: &str, number_sort: f32) -> String {
+        format!(
+            r#"{{"seriesId": "0A1", "seriesTitle": "{}", "metadata": {{"numberSort": {}}}}}"#,
+            series_title, number_sort
+        )
+    }
+
+    #[test]
+    fn reads_read_books_of_every_page() {
+        let page = |page: &str, books: &[String], last: bool| {
+            mock("GET", "/api/v1/books")
+                .match_header("authorization", Matcher::Regex("^Basic ".to_string()))
+                .match_query(Matcher::AllOf(vec![
+                    Matcher::UrlEncoded("read_status".into(), "READ".into()),
+                    Matcher::UrlEncoded("page".into(), page.into()),
+                ]))
+                .with_body(format!(
+                    r#"{{"content": [{}], "last": {}}}"#,
+                    books.join(","),
+                    last
+                ))
+                .create()
+        };
+        let _first = page("0", &[book("Berserk", 1.0), book("Berserk", 2.5)], false);
+        let _second = page("1", &[book("Vinland Saga", 7.0)], true);
+
+        let progress =
+            read_progress(&mockito::server_url(), "user", "pass", &Client::new()).unwrap();
+        let chapters: Vec<(&str, Chapter)> = progress
+            .iter()
+            .map(|p| (p.title.as_str(), p.chapter.clone()))
+            .collect();
+        assert_eq!(
+            chapters,
+            [
+                ("Berserk", Chapter::Number(1)),
+                ("Berserk", Chapter::Decimal(2.5)),
+                ("Vinland Saga", Chapter::Number(7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn fails_on_error_status() {
+        let _books = mock("GET", "/api/v1/books")
+            .match_query(Matcher::Any)
+            .with_status(401)
+            .create();
+        assert!(read_progress(&mockito::server_url(), "user", "wrong", &Client::new()).is_err());
+    }
+}
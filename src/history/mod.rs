@@ -0,0 +1,262 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use reqwest::blocking::Client;
+use std::path::Path;
+
+use crate::anilist::model::MediaListStatus;
+use crate::anilist::request;
+use crate::error::MendoError;
+use crate::output::{self, Record};
+use crate::progress::{Chapter, ChapterPolicy, Read};
+use crate::util::{self, MendoConfig};
+
+pub mod kavita;
+pub mod komga;
+pub mod tachiyomi;
+pub mod yacreader;
+
+// Reading progress of a single series as recorded by another reader
+#[derive(Debug, Clone)]
+pub struct ReadProgress {
+    pub title: String,
+    // Some readers (e.g. Tachiyomi) already know the AniList media id
+    pub media_id: Option<i32>,
+    // Left as the reader names it, `parser.chapter_policy` decides what it counts as
+    pub chapter: Chapter,
+}
+
+// The furthest chapter of a series, as Anilist counts it
+#[derive(Debug, Clone)]
+pub struct SeriesProgress {
+    pub title: String,
+    pub media_id: Option<i32>,
+    pub chapter: i32,
+}
+
+// Keeps only the furthest chapter of every series, counted the same way as `update` does
+pub fn merge_progress(entries: Vec<ReadProgress>, policy: &ChapterPolicy) -> Vec<SeriesProgress> {
+    let mut merged: Vec<SeriesProgress> = Vec::new();
+    for entry in entries {
+        let chapter = match entry.chapter.to_read(policy) {
+            Read::Chapter(chapter) => chapter,
+            Read::Skip(reason) => {
+                debug!("Skipping a chapter of `{}`: {}", entry.title, reason);
+                continue;
+            }
+            // Specials only move `update` one chapter forward, which says nothing
+            // about how far a reader got
+            read => {
+                debug!("Skipping a chapter of `{}`: {:?}", entry.title, read);
+                continue;
+            }
+        };
+        match merged.iter_mut().find(|e| e.title == entry.title) {
+            Some(existing) => {
+                if chapter > existing.chapter {
+                    existing.chapter = chapter;
+                }
+                if existing.media_id.is_none() {
+                    existing.media_id = entry.media_id;
+                }
+            }
+            None => merged.push(SeriesProgress {
+                title: entry.title,
+                media_id: entry.media_id,
+                chapter,
+            }),
+        }
+    }
+    merged
+}
+
+// A dry run only looks the entry up, since `get_eid_and_progress` creates missing ones
+fn list_progress(
+    cfg: &mut MendoConfig,
+    user_id: i32,
+    media_id: i32,
+    dry_run: bool,
+    client: &Client,
+) -> Result<(Option<i32>, i32)> {
+    if dry_run {
+        let media_list = util::find_media_list(
+            cfg,
+            user_id,
+            media_id,
+            Some(MediaListStatus::Dropped),
+            client,
+        )?;
+        Ok(media_list.map_or((None, 0), |media_list| {
+            (Some(media_list.entry_id), media_list.progress)
+        }))
+    } else {
+        let (entry_id, progress) = util::get_eid_and_progress(cfg, user_id, media_id, client)?;
+        Ok((Some(entry_id), progress))
+    }
+}
+
+// Moves a single series forward when the reader is ahead of Anilist
+fn apply_entry(
+    cfg: &mut MendoConfig,
+    user_id: i32,
+    media_id: i32,
+    entry: &SeriesProgress,
+    dry_run: bool,
+    client: &Client,
+) -> Result<()> {
+    let (entry_id, progress) = list_progress(cfg, user_id, media_id, dry_run, client)?;
+    if entry.chapter <= progress {
+        debug!(
+            "`{}` is already at chapter {} on Anilist (reader: {})",
+            entry.title, progress, entry.chapter
+        );
+        return Ok(());
+    }
+    output::emit(
+        &format!(
+            "`{}` (mediaId: {}): {} -> {}",
+            entry.title, media_id, progress, entry.chapter
+        ),
+        Record {
+            event: "progress",
+            media_id: Some(media_id),
+            title: Some(entry.title.clone()),
+            old_progress: Some(progress),
+            new_progress: Some(entry.chapter),
+            dry_run: Some(dry_run),
+            ..Record::default()
+        },
+    );
+    if let (false, Some(entry_id)) = (dry_run, entry_id) {
+        request::update_media(cfg, entry_id, entry.chapter, client)?;
+    }
+    Ok(())
+}
+
+pub fn apply_progress(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    entries: Vec<ReadProgress>,
+    dry_run: bool,
+    client: &Client,
+) -> Result<()> {
+    let user_id = util::get_user_id(cfg, data_dir, client)?;
+    let entries = merge_progress(entries, &cfg.parser.chapter_policy);
+    info!("Got reading progress of {} series", entries.len());
+
+    for entry in entries {
+        if entry.chapter <= 0 {
+            debug!("Skipping `{}` because nothing has been read", entry.title);
+            continue;
+        }
+        let media_id = match entry.media_id {
+            Some(media_id) => media_id,
+            None => match util::get_media_id_by_name(cfg, data_dir, &entry.title, None, client) {
+                Ok(media_id) => media_id,
+                Err(e) => {
+                    warn!("Could not resolve `{}`: {}", entry.title, e);
+                    output::emit(
+                        &format!("`{}`: could not find it on Anilist, skipped", entry.title),
+                        Record {
+                            event: "skipped",
+                            title: Some(entry.title.clone()),
+                            message: Some(e.to_string()),
+                            ..Record::default()
+                        },
+                    );
+                    continue;
+                }
+            },
+        };
+        // One failing series should not cost the progress of all the others,
+        // but without a valid token none of them would get through
+        if let Err(e) = apply_entry(cfg, user_id, media_id, &entry, dry_run, client) {
+            if let Some(MendoError::Unauthorized) = e.downcast_ref::<MendoError>() {
+                return Err(e);
+            }
+            warn!("Could not update `{}`: {}", entry.title, e);
+            output::emit(
+                &format!("`{}`: could not update it on Anilist, skipped", entry.title),
+                Record {
+                    event: "skipped",
+                    media_id: Some(media_id),
+                    title: Some(entry.title.clone()),
+                    message: Some(e.to_string()),
+                    ..Record::default()
+                },
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::DecimalPolicy;
+
+    fn progress(title: &str, media_id: Option<i32>, chapter: f64) -> ReadProgress {
+        ReadProgress {
+            title: title.to_string(),
+            media_id,
+            chapter: Chapter::from_number(chapter),
+        }
+    }
+
+    #[test]
+    fn merge_keeps_furthest_chapter_and_known_id() {
+        let merged = merge_progress(
+            vec![
+                progress("Berserk", None, 12.0),
+                progress("Vinland Saga", Some(30002), 4.0),
+                progress("Berserk", Some(30002), 10.0),
+                progress("Berserk", Some(1), 15.0),
+            ],
+            &ChapterPolicy::default(),
+        );
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].title, "Berserk");
+        assert_eq!(merged[0].chapter, 15);
+        // The first id seen wins, later ones do not overwrite it
+        assert_eq!(merged[0].media_id, Some(30002));
+        assert_eq!(merged[1].title, "Vinland Saga");
+        assert_eq!(merged[1].chapter, 4);
+    }
+
+    #[test]
+    fn merge_keeps_series_order() {
+        let merged = merge_progress(
+            vec![
+                progress("B", None, 1.0),
+                progress("A", None, 1.0),
+                progress("B", None, 2.0),
+            ],
+            &ChapterPolicy::default(),
+        );
+        let titles: Vec<&str> = merged.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["B", "A"]);
+    }
+
+    #[test]
+    fn merge_follows_the_chapter_policy() {
+        let entries = || {
+            vec![
+                progress("Berserk", None, 12.0),
+                progress("Berserk", None, 13.5),
+                ReadProgress {
+                    title: "Berserk".to_string(),
+                    media_id: None,
+                    chapter: Chapter::Special("Extra".to_string()),
+                },
+            ]
+        };
+        let floor = merge_progress(entries(), &ChapterPolicy::default());
+        assert_eq!(floor[0].chapter, 13);
+
+        let skip = ChapterPolicy {
+            decimal: DecimalPolicy::Skip,
+            ..ChapterPolicy::default()
+        };
+        assert_eq!(merge_progress(entries(), &skip)[0].chapter, 12);
+    }
+}
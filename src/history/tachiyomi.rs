@@ -0,0 +1,189 @@
+use anyhow::Result;
+use flate2::read::GzDecoder;
+use log::debug;
+use prost::Message;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::ReadProgress;
+use crate::progress::Chapter;
+
+// Tachiyomi/Mihon identify AniList tracking entries with this sync id
+const ANILIST_SYNC_ID: i32 = 2;
+
+// Only the fields mendo cares about are declared, the rest is skipped by prost.
+// Field numbers follow the `.proto.gz` backup format of Tachiyomi/Mihon.
+#[derive(Clone, PartialEq, Message)]
+pub struct Backup {
+    #[prost(message, repeated, tag = "1")]
+    pub backup_manga: Vec<BackupManga>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BackupManga {
+    #[prost(string, tag = "3")]
+    pub title: String,
+    #[prost(message, repeated, tag = "16")]
+    pub chapters: Vec<BackupChapter>,
+    #[prost(message, repeated, tag = "18")]
+    pub tracking: Vec<BackupTracking>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BackupChapter {
+    #[prost(bool, tag = "4")]
+    pub read: bool,
+    #[prost(float, tag = "9")]
+    pub chapter_number: f32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct BackupTracking {
+    #[prost(int32, tag = "1")]
+    pub sync_id: i32,
+    // Deprecated in favour of media_id but still written by older versions
+    #[prost(int32, tag = "3")]
+    pub media_id_int: i32,
+    #[prost(float, tag = "6")]
+    pub last_chapter_read: f32,
+    #[prost(int64, tag = "100")]
+    pub media_id: i64,
+}
+
+pub fn read_backup(path: &Path) -> Result<Vec<ReadProgress>> {
+    let mut buf = Vec::new();
+    GzDecoder::new(File::open(path)?).read_to_end(&mut buf)?;
+    let backup = Backup::decode(buf.as_slice())?;
+    debug!(
+        "Decoded Tachiyomi backup with {} manga",
+        backup.backup_manga.len()
+    );
+
+    let mut progress = Vec::new();
+    for manga in backup.backup_manga {
+        let anilist = manga.tracking.iter().find(|t| t.sync_id == ANILIST_SYNC_ID);
+        let media_id = anilist.and_then(|t| {
+            if t.media_id > 0 {
+                Some(t.media_id as i32)
+            } else if t.media_id_int > 0 {
+                Some(t.media_id_int)
+            } else {
+                None
+            }
+        });
+        // Every read chapter is kept as is, `merge_progress` picks the furthest one
+        // the chapter policy counts
+        let read = manga
+            .chapters
+            .iter()
+            .filter(|c| c.read)
+            .map(|c| c.chapter_number);
+        let tracked = anilist
+            .map(|t| t.last_chapter_read)
+            .filter(|&chapter| chapter > 0.0);
+        for chapter in read.chain(tracked) {
+            progress.push(ReadProgress {
+                title: manga.title.clone(),
+                media_id,
+                chapter: Chapter::from_number(chapter.into()),
+            });
+        }
+    }
+
+    Ok(progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn chapter(chapter_number: f32, read: bool) -> BackupChapter {
+        BackupChapter {
+            read,
+            chapter_number,
+        }
+    }
+
+    fn write_backup(backup: &Backup) -> tempfile::NamedTempFile {
+        let mut buf = Vec::new();
+        backup.encode(&mut buf).unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut encoder = GzEncoder::new(file.reopen().unwrap(), Compression::default());
+        encoder.write_all(&buf).unwrap();
+        encoder.finish().unwrap();
+        file
+    }
+
+    #[test]
+    fn reads_progress_and_anilist_ids() {
+        let backup = Backup {
+            backup_manga: vec![
+                BackupManga {
+                    title: "Berserk".to_string(),
+                    chapters: vec![chapter(1.0, true), chapter(2.5, true), chapter(3.0, false)],
+                    tracking: vec![
+                        // MyAnimeList tracking is ignored
+                        BackupTracking {
+                            sync_id: 1,
+                            media_id_int: 2,
+                            last_chapter_read: 40.0,
+                            media_id: 2,
+                        },
+                        BackupTracking {
+                            sync_id: ANILIST_SYNC_ID,
+                            media_id_int: 0,
+                            last_chapter_read: 1.0,
+                            media_id: 30002,
+                        },
+                    ],
+                },
+                BackupManga {
+                    title: "Vinland Saga".to_string(),
+                    chapters: vec![chapter(1.0, true)],
+                    tracking: vec![BackupTracking {
+                        sync_id: ANILIST_SYNC_ID,
+                        media_id_int: 30642,
+                        last_chapter_read: 7.0,
+                        media_id: 0,
+                    }],
+                },
+                BackupManga {
+                    title: "Untracked".to_string(),
+                    chapters: vec![chapter(1.0, false)],
+                    tracking: vec![],
+                },
+            ],
+        };
+        let file = write_backup(&backup);
+
+        let progress = read_backup(file.path()).unwrap();
+        let progress: Vec<(&str, Option<i32>, Chapter)> = progress
+            .iter()
+            .map(|p| (p.title.as_str(), p.media_id, p.chapter.clone()))
+            .collect();
+        // Decimals are left to the chapter policy, the deprecated id is used when the
+        // new one is missing, and the tracker can be further along than the chapters
+        // marked as read. Nothing read means nothing to report.
+        assert_eq!(
+            progress,
+            [
+                ("Berserk", Some(30002), Chapter::Number(1)),
+                ("Berserk", Some(30002), Chapter::Decimal(2.5)),
+                ("Berserk", Some(30002), Chapter::Number(1)),
+                ("Vinland Saga", Some(30642), Chapter::Number(1)),
+                ("Vinland Saga", Some(30642), Chapter::Number(7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_backups() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(b"not gzip").unwrap();
+        assert!(read_backup(file.path()).is_err());
+    }
+}
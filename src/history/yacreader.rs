@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Result};
+use log::{debug, error};
+use rusqlite::types::Value;
+use rusqlite::{Connection, OpenFlags, NO_PARAMS};
+use std::path::Path;
+
+use super::ReadProgress;
+use crate::progress::{parse_chapter, Chapter};
+
+// Comics without series metadata are grouped by the folder they live in
+const READ_COMICS: &str = "
+SELECT COALESCE(NULLIF(ci.series, ''), f.name), ci.number, c.fileName
+FROM comic c
+JOIN comic_info ci ON c.comicInfoId = ci.id
+JOIN folder f ON c.parentId = f.id
+WHERE ci.read = 1
+";
+
+// Accepts either the library root or the `library.ydb` file itself
+pub fn read_library(path: &Path) -> Result<Vec<ReadProgress>> {
+    let db_path = if path.is_dir() {
+        path.join(".yacreaderlibrary").join("library.ydb")
+    } else {
+        path.to_path_buf()
+    };
+    if !db_path.exists() {
+        error!("Could not find YACReader library at {}", db_path.display());
+        return Err(anyhow!(
+            "Could not find YACReader library at {}",
+            db_path.display()
+        ));
+    }
+
+    let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(READ_COMICS)?;
+    let rows = stmt.query_map(NO_PARAMS, |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, Value>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    })?;
+
+    let mut progress = Vec::new();
+    for row in rows {
+        let (title, number, filename) = row?;
+        // Older libraries store the number as an integer, newer ones as text
+        let chapter = match number {
+            Value::Integer(n) => Some(Chapter::Number(n as i32)),
+            Value::Real(n) => Some(Chapter::from_number(n)),
+            Value::Text(n) => n.trim().parse().ok().map(Chapter::from_number),
+            _ => None,
+        }
+        .or_else(|| parse_chapter(&filename, &title));
+        match chapter {
+            Some(chapter) => progress.push(ReadProgress {
+                title,
+                media_id: None,
+                chapter,
+            }),
+            None => debug!("Could not get chapter number of `{}`, skipped", filename),
+        }
+    }
+    debug!("Found {} read comics in YACReader library", progress.len());
+
+    Ok(progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just the columns mendo reads of a real library.ydb
+    const SCHEMA: &str = "
+CREATE TABLE folder (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+CREATE TABLE comic_info (id INTEGER PRIMARY KEY, series TEXT, number, read BOOLEAN DEFAULT 0);
+CREATE TABLE comic (
+    id INTEGER PRIMARY KEY,
+    parentId INTEGER NOT NULL,
+    comicInfoId INTEGER NOT NULL,
+    fileName TEXT NOT NULL
+);
+INSERT INTO folder VALUES (1, 'Berserk'), (2, 'Vinland Saga');
+INSERT INTO comic_info VALUES
+    (1, '', 12, 1),
+    (2, 'Vinland Saga', '7.5', 1),
+    (3, NULL, NULL, 1),
+    (4, 'Berserk', 40, 0),
+    (5, NULL, NULL, 1),
+    (6, NULL, NULL, 1);
+INSERT INTO comic VALUES
+    (1, 1, 1, 'Berserk 012.cbz'),
+    (2, 2, 2, 'Vinland Saga 007.5.cbz'),
+    (3, 1, 3, 'Berserk c013.cbz'),
+    (4, 1, 4, 'Berserk 040.cbz'),
+    (5, 2, 5, 'Extra.cbz'),
+    (6, 1, 6, 'Berserk v01 (2003).cbz');
+";
+
+    fn library() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let db_dir = dir.path().join(".yacreaderlibrary");
+        std::fs::create_dir(&db_dir).unwrap();
+        Connection::open(db_dir.join("library.ydb"))
+            .unwrap()
+            .execute_batch(SCHEMA)
+            .unwrap();
+        dir
+    }
+
+    #[test]
+    fn reads_read_comics_of_library() {
+        let dir = library();
+        let mut progress: Vec<(String, Chapter)> = read_library(dir.path())
+            .unwrap()
+            .into_iter()
+            .map(|p| (p.title, p.chapter))
+            .collect();
+        progress.sort_by(|a, b| a.0.cmp(&b.0));
+        // Unread comics and those without any chapter are left out (a volume or a year
+        // in the filename is not one), the folder stands in for a missing series and the
+        // filename for a missing number. Extras are left to the chapter policy.
+        assert_eq!(
+            progress,
+            [
+                ("Berserk".to_string(), Chapter::Number(12)),
+                ("Berserk".to_string(), Chapter::Number(13)),
+                ("Vinland Saga".to_string(), Chapter::Decimal(7.5)),
+                (
+                    "Vinland Saga".to_string(),
+                    Chapter::Special("Extra".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_the_database_file() {
+        let dir = library();
+        let db_path = dir.path().join(".yacreaderlibrary").join("library.ydb");
+        assert_eq!(read_library(&db_path).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn fails_without_library() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_library(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,587 @@
+use anyhow::{anyhow, Result};
+use log::{debug, error, info, warn};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, remove_file};
+use std::path::Path;
+use std::{thread, time};
+
+use crate::anilist::model::{MediaListStatus, MediaType};
+use crate::anilist::request;
+use crate::export;
+use crate::output::{self, Record};
+use crate::util::{self, MendoConfig};
+
+// A single title read from the imported file
+#[derive(Debug, Clone)]
+pub struct ImportEntry {
+    pub title: String,
+    pub id_mal: Option<i32>,
+    pub media_id: Option<i32>,
+    pub status: MediaListStatus,
+    pub progress: i32,
+}
+
+#[derive(Deserialize, Debug)]
+struct CsvRow {
+    title: String,
+    progress: i32,
+    status: String,
+    #[serde(default)]
+    media_id: Option<i32>,
+    #[serde(default)]
+    id_mal: Option<i32>,
+}
+
+// Written after every applied change so an interrupted import can be resumed
+#[derive(Serialize, Deserialize, Debug)]
+struct ImportState {
+    source: String,
+    applied: Vec<i32>,
+}
+
+#[derive(Debug)]
+struct Change {
+    title: String,
+    media_id: i32,
+    old: Option<(MediaListStatus, i32)>,
+    status: MediaListStatus,
+    progress: i32,
+}
+
+// Accepts the statuses of both MAL exports (names or numbers) and Anilist
+pub fn parse_status(status: &str) -> Option<MediaListStatus> {
+    match status.trim().to_lowercase().as_str() {
+        "reading" | "current" | "1" => Some(MediaListStatus::Current),
+        "completed" | "2" => Some(MediaListStatus::Completed),
+        "on-hold" | "on hold" | "paused" | "3" => Some(MediaListStatus::Paused),
+        "dropped" | "4" => Some(MediaListStatus::Dropped),
+        "plan to read" | "planning" | "6" => Some(MediaListStatus::Planning),
+        "repeating" | "rereading" => Some(MediaListStatus::Repeating),
+        _ => None,
+    }
+}
+
+pub fn read_mal_xml(path: &Path) -> Result<Vec<ImportEntry>> {
+    let xml = fs::read_to_string(path)?;
+    let mut entries = Vec::new();
+    for record in util::read_xml_records(&xml, "manga")? {
+        let get = |key: &str| record.get(key).map(|v| v.trim()).unwrap_or("");
+        let title = get("manga_title").to_string();
+        let status = match parse_status(get("my_status")) {
+            Some(MediaListStatus::Current) if get("my_rereading") == "YES" => {
+                MediaListStatus::Repeating
+            }
+            Some(status) => status,
+            None => {
+                warn!(
+                    "`{}` has an unknown status `{}`, skipped",
+                    title,
+                    get("my_status")
+                );
+                continue;
+            }
+        };
+        entries.push(ImportEntry {
+            title,
+            id_mal: get("manga_mangadb_id").parse().ok().filter(|id| *id > 0),
+            media_id: None,
+            status,
+            progress: get("my_read_chapters").parse().unwrap_or(0),
+        });
+    }
+    Ok(entries)
+}
+
+pub fn read_csv(path: &Path) -> Result<Vec<ImportEntry>> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let mut entries = Vec::new();
+    for row in reader.deserialize() {
+        let row: CsvRow = row?;
+        match parse_status(&row.status) {
+            Some(status) => entries.push(ImportEntry {
+                title: row.title,
+                id_mal: row.id_mal,
+                media_id: row.media_id,
+                status,
+                progress: row.progress,
+            }),
+            None => warn!(
+                "`{}` has an unknown status `{}`, skipped",
+                row.title, row.status
+            ),
+        }
+    }
+    Ok(entries)
+}
+
+pub fn read_file(path: &Path) -> Result<Vec<ImportEntry>> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("xml") => read_mal_xml(path),
+        Some("csv") => read_csv(path),
+        _ => {
+            error!("Unsupported import file `{}`", path.display());
+            Err(anyhow!(
+                "Unsupported import file `{}`. Use a MyAnimeList .xml export or a .csv file",
+                path.display()
+            ))
+        }
+    }
+}
+
+// Prefers the MyAnimeList id, titles can resolve to a different adaptation
+fn resolve(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    entry: &ImportEntry,
+    client: &Client,
+) -> Result<i32> {
+    if let Some(media_id) = entry.media_id {
+        return Ok(media_id);
+    }
+    if let Some(id_mal) = entry.id_mal {
+        match request::search_media_by_mal_id(cfg, id_mal, MediaType::Manga, client) {
+            Ok(query_result) => {
+                if let Some(media_resp) = query_result.data {
+                    return Ok(media_resp.media.media_id);
+                }
+            }
+            Err(e) => debug!("Could not find MyAnimeList id `{}`: {}", id_mal, e),
+        }
+    }
+    util::get_media_id_by_name(cfg, data_dir, &entry.title, None, client)
+}
+
+fn load_state(state_path: &Path, source: &str) -> Result<ImportState> {
+    if state_path.exists() {
+        let state: ImportState = serde_yaml::from_str(&fs::read_to_string(state_path)?)?;
+        if state.source == source {
+            info!(
+                "Resuming previous import of {}, {} entries already applied",
+                source,
+                state.applied.len()
+            );
+            return Ok(state);
+        }
+        debug!(
+            "Found state of another import ({}), starting over",
+            state.source
+        );
+    }
+    Ok(ImportState {
+        source: source.to_string(),
+        applied: Vec::new(),
+    })
+}
+
+// Written next to it first and renamed over it, so a crash never leaves a truncated state
+fn save_state(state_path: &Path, state: &ImportState) -> Result<()> {
+    let mut tmp = state_path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, serde_yaml::to_string(state)?)?;
+    fs::rename(&tmp, state_path)?;
+    Ok(())
+}
+
+// What becomes of an imported entry, given the one on Anilist
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Apply,
+    // Anilist has it like this already
+    Unchanged,
+    // Anilist is ahead, for the reason given
+    Keep(String),
+}
+
+fn is_finished(status: &MediaListStatus) -> bool {
+    matches!(
+        status,
+        MediaListStatus::Completed | MediaListStatus::Repeating
+    )
+}
+
+fn compare(old: Option<&(MediaListStatus, i32)>, entry: &ImportEntry) -> Verdict {
+    match old {
+        None => Verdict::Apply,
+        Some((status, progress)) if *status == entry.status && *progress == entry.progress => {
+            Verdict::Unchanged
+        }
+        // Never go back in progress, Anilist is usually the more up to date one
+        Some((_, progress)) if *progress > entry.progress => Verdict::Keep(format!(
+            "Anilist is ahead ({} > {})",
+            progress, entry.progress
+        )),
+        // Nor from a finished series back to one that is still being read
+        Some((status, progress))
+            if *progress == entry.progress
+                && is_finished(status)
+                && !is_finished(&entry.status) =>
+        {
+            Verdict::Keep(format!("Anilist has it as {:?}", status))
+        }
+        Some(_) => Verdict::Apply,
+    }
+}
+
+fn confirm(message: &str) -> Result<bool> {
+    let answer = output::ask(&format!("{} [y/N] ", message))?;
+    Ok(answer.eq_ignore_ascii_case("y"))
+}
+
+pub fn import(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    path: &Path,
+    dry_run: bool,
+    assume_yes: bool,
+    delay: time::Duration,
+    client: &Client,
+) -> Result<()> {
+    let entries = read_file(path)?;
+    info!("Read {} entries from {}", entries.len(), path.display());
+
+    let state_path = data_dir.join("import_state.yml");
+    let source = fs::canonicalize(path)?.display().to_string();
+    let mut state = load_state(&state_path, &source)?;
+
+    let (_, current) = export::fetch_entries(cfg, data_dir, client)?;
+    let current: HashMap<i32, (MediaListStatus, i32)> = current
+        .into_iter()
+        .filter_map(|e| {
+            e.media
+                .as_ref()
+                .map(|m| (m.media_id, (e.status.clone(), e.progress)))
+        })
+        .collect();
+
+    let mut changes = Vec::new();
+    for entry in entries {
+        let media_id = match resolve(cfg, data_dir, &entry, client) {
+            Ok(media_id) => media_id,
+            Err(e) => {
+                warn!("Could not resolve `{}`: {}", entry.title, e);
+                output::emit(
+                    &format!("! {}: could not find it on Anilist, skipped", entry.title),
+                    Record {
+                        event: "skipped",
+                        title: Some(entry.title.clone()),
+                        message: Some(e.to_string()),
+                        ..Record::default()
+                    },
+                );
+                continue;
+            }
+        };
+        if state.applied.contains(&media_id) {
+            continue;
+        }
+        let old = current.get(&media_id).cloned();
+        match compare(old.as_ref(), &entry) {
+            Verdict::Apply => (),
+            Verdict::Unchanged => continue,
+            Verdict::Keep(reason) => {
+                let (status, progress) = old.expect("Safe because only existing entries are kept");
+                output::emit(
+                    &format!("= {}: kept, {}", entry.title, reason),
+                    Record {
+                        event: "kept",
+                        media_id: Some(media_id),
+                        title: Some(entry.title.clone()),
+                        old_progress: Some(progress),
+                        new_progress: Some(entry.progress),
+                        old_status: Some(status),
+                        new_status: Some(entry.status.clone()),
+                        message: Some(reason),
+                        ..Record::default()
+                    },
+                );
+                continue;
+            }
+        }
+        changes.push(Change {
+            title: entry.title,
+            media_id,
+            old,
+            status: entry.status,
+            progress: entry.progress,
+        });
+    }
+
+    for change in &changes {
+        let text = match &change.old {
+            Some((status, progress)) => format!(
+                "~ {}: {:?} {} -> {:?} {}",
+                change.title, status, progress, change.status, change.progress
+            ),
+            None => format!(
+                "+ {}: {:?} {}",
+                change.title, change.status, change.progress
+            ),
+        };
+        output::emit(
+            &text,
+            Record {
+                event: "change",
+                media_id: Some(change.media_id),
+                title: Some(change.title.clone()),
+                old_progress: change.old.as_ref().map(|(_, progress)| *progress),
+                new_progress: Some(change.progress),
+                old_status: change.old.as_ref().map(|(status, _)| status.clone()),
+                new_status: Some(change.status.clone()),
+                dry_run: Some(dry_run),
+                ..Record::default()
+            },
+        );
+    }
+    output::say(&format!("{} changes to apply", changes.len()));
+
+    if changes.is_empty() {
+        if !dry_run && state_path.exists() {
+            remove_file(&state_path)?;
+        }
+        return Ok(());
+    }
+    if dry_run {
+        return Ok(());
+    }
+    if !assume_yes && !confirm("Apply these changes to Anilist?")? {
+        output::emit(
+            "Import cancelled.",
+            Record {
+                event: "import_cancelled",
+                count: Some(changes.len()),
+                ..Record::default()
+            },
+        );
+        return Ok(());
+    }
+
+    for (i, change) in changes.iter().enumerate() {
+        if i > 0 {
+            // Stay well below Anilist's rate limit, query_graphql still handles 429 if we hit it
+            thread::sleep(delay);
+        }
+        request::create_new_entry(
+            cfg,
+            change.media_id,
+            change.status.clone(),
+            change.progress,
+            client,
+        )?;
+        state.applied.push(change.media_id);
+        save_state(&state_path, &state)?;
+    }
+
+    if state_path.exists() {
+        remove_file(&state_path)?;
+    }
+    output::emit(
+        &format!("Import finished, {} changes applied.", changes.len()),
+        Record {
+            event: "import_finished",
+            count: Some(changes.len()),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_fixture(dir: &tempfile::TempDir, name: &str, content: &str) -> std::path::PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn parses_status_names_and_numbers() {
+        for (status, expected) in &[
+            ("Reading", Some(MediaListStatus::Current)),
+            ("1", Some(MediaListStatus::Current)),
+            ("Completed", Some(MediaListStatus::Completed)),
+            ("2", Some(MediaListStatus::Completed)),
+            ("On-Hold", Some(MediaListStatus::Paused)),
+            ("3", Some(MediaListStatus::Paused)),
+            ("Dropped", Some(MediaListStatus::Dropped)),
+            ("4", Some(MediaListStatus::Dropped)),
+            ("Plan to Read", Some(MediaListStatus::Planning)),
+            ("6", Some(MediaListStatus::Planning)),
+            (" PLANNING ", Some(MediaListStatus::Planning)),
+            ("rereading", Some(MediaListStatus::Repeating)),
+            ("5", None),
+            ("Wishlist", None),
+        ] {
+            assert_eq!(parse_status(status), *expected, "{}", status);
+        }
+    }
+
+    #[test]
+    fn reads_mal_xml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            &dir,
+            "mangalist.xml",
+            r#"<?xml version="1.0" encoding="UTF-8" ?>
+<myanimelist>
+  <myinfo><user_name>me</user_name></myinfo>
+  <manga>
+    <manga_mangadb_id>2</manga_mangadb_id>
+    <manga_title><![CDATA[Berserk]]></manga_title>
+    <my_read_chapters>364</my_read_chapters>
+    <my_status>Reading</my_status>
+    <my_rereading>YES</my_rereading>
+  </manga>
+  <manga>
+    <manga_mangadb_id>0</manga_mangadb_id>
+    <manga_title>Yotsuba&amp;!</manga_title>
+    <my_read_chapters>12</my_read_chapters>
+    <my_status>2</my_status>
+    <my_rereading>NO</my_rereading>
+  </manga>
+  <manga>
+    <manga_mangadb_id>3</manga_mangadb_id>
+    <manga_title>Unknown</manga_title>
+    <my_status>Wishlist</my_status>
+  </manga>
+  <manga>
+    <manga_mangadb_id>13</manga_mangadb_id>
+    <manga_title>One Piece</manga_title>
+    <my_read_chapters></my_read_chapters>
+    <my_status>Plan to Read</my_status>
+  </manga>
+</myanimelist>
+"#,
+        );
+
+        let entries = read_mal_xml(&path).unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].title, "Berserk");
+        assert_eq!(entries[0].id_mal, Some(2));
+        assert_eq!(entries[0].status, MediaListStatus::Repeating);
+        assert_eq!(entries[0].progress, 364);
+        // MAL writes 0 for entries it has no id for
+        assert_eq!(entries[1].title, "Yotsuba&!");
+        assert_eq!(entries[1].id_mal, None);
+        assert_eq!(entries[1].status, MediaListStatus::Completed);
+        assert_eq!(entries[1].progress, 12);
+        assert_eq!(entries[2].title, "One Piece");
+        assert_eq!(entries[2].status, MediaListStatus::Planning);
+        assert_eq!(entries[2].progress, 0);
+        assert!(entries.iter().all(|entry| entry.media_id.is_none()));
+    }
+
+    #[test]
+    fn reads_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            &dir,
+            "list.csv",
+            "title,progress,status,media_id,id_mal\n\
+             Berserk,364,current,30002,2\n\
+             Yotsuba&!,12,4,,\n\
+             Unknown,1,wishlist,,\n",
+        );
+
+        let entries = read_csv(&path).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "Berserk");
+        assert_eq!(entries[0].media_id, Some(30002));
+        assert_eq!(entries[0].id_mal, Some(2));
+        assert_eq!(entries[0].status, MediaListStatus::Current);
+        assert_eq!(entries[0].progress, 364);
+        assert_eq!(entries[1].title, "Yotsuba&!");
+        assert_eq!(entries[1].media_id, None);
+        assert_eq!(entries[1].id_mal, None);
+        assert_eq!(entries[1].status, MediaListStatus::Dropped);
+    }
+
+    #[test]
+    fn resumes_the_same_import_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = write_fixture(
+            &dir,
+            "import_state.yml",
+            "source: /home/me/mangalist.xml\napplied:\n  - 30002\n  - 30013\n",
+        );
+
+        let state = load_state(&state_path, "/home/me/mangalist.xml").unwrap();
+        assert_eq!(state.applied, vec![30002, 30013]);
+
+        // Another file starts over and applies everything again
+        let state = load_state(&state_path, "/home/me/other.csv").unwrap();
+        assert_eq!(state.source, "/home/me/other.csv");
+        assert!(state.applied.is_empty());
+
+        let state = load_state(&dir.path().join("missing.yml"), "/home/me/mangalist.xml").unwrap();
+        assert!(state.applied.is_empty());
+    }
+
+    #[test]
+    fn saves_the_state_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_path = write_fixture(&dir, "import_state.yml", "source: old\napplied: []\n");
+        let state = ImportState {
+            source: "/home/me/mangalist.xml".to_string(),
+            applied: vec![30002],
+        };
+        save_state(&state_path, &state).unwrap();
+
+        let state = load_state(&state_path, "/home/me/mangalist.xml").unwrap();
+        assert_eq!(state.applied, vec![30002]);
+        assert!(!dir.path().join("import_state.yml.tmp").exists());
+    }
+
+    #[test]
+    fn never_demotes_what_anilist_is_ahead_on() {
+        let entry = |status: MediaListStatus, progress: i32| ImportEntry {
+            title: "Berserk".to_string(),
+            id_mal: None,
+            media_id: None,
+            status,
+            progress,
+        };
+        use MediaListStatus::*;
+        for (old, new, verdict) in &[
+            (None, entry(Current, 10), Verdict::Apply),
+            (Some((Current, 10)), entry(Current, 10), Verdict::Unchanged),
+            (Some((Current, 10)), entry(Current, 12), Verdict::Apply),
+            (Some((Current, 10)), entry(Completed, 10), Verdict::Apply),
+            (Some((Paused, 10)), entry(Current, 10), Verdict::Apply),
+            (
+                Some((Current, 12)),
+                entry(Completed, 10),
+                Verdict::Keep("Anilist is ahead (12 > 10)".to_string()),
+            ),
+            (
+                Some((Completed, 100)),
+                entry(Current, 100),
+                Verdict::Keep("Anilist has it as Completed".to_string()),
+            ),
+            (
+                Some((Repeating, 100)),
+                entry(Dropped, 100),
+                Verdict::Keep("Anilist has it as Repeating".to_string()),
+            ),
+            (
+                Some((Completed, 100)),
+                entry(Repeating, 100),
+                Verdict::Apply,
+            ),
+            (Some((Completed, 100)), entry(Current, 101), Verdict::Apply),
+        ] {
+            assert_eq!(
+                compare(old.as_ref(), new),
+                *verdict,
+                "{:?} -> {:?}",
+                old,
+                new
+            );
+        }
+    }
+}
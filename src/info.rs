@@ -0,0 +1,143 @@
+use anyhow::Result;
+use log::debug;
+use reqwest::blocking::Client;
+use std::path::Path;
+
+use crate::anilist::model::{FuzzyDate, Media, MediaList, MediaListStatus, MediaType};
+use crate::anilist::request;
+use crate::error::MendoError;
+use crate::export::media_title;
+use crate::output::{self, Record};
+use crate::util::{self, MendoConfig};
+
+// Goes through media_data.txt like `update` does, so it shows what an archive would map to
+fn resolve(cfg: &mut MendoConfig, data_dir: &Path, query: &str, client: &Client) -> Result<Media> {
+    let local_media_data = data_dir.join("media_data.txt");
+    let media_id = match query.parse::<i32>() {
+        Ok(media_id) => Some(media_id),
+        Err(_) => util::find_local_media_id(&local_media_data, query)?,
+    };
+    match media_id {
+        Some(media_id) => request::query_media(cfg, media_id, client)?
+            .data
+            .map(|media_resp| media_resp.media)
+            .ok_or_else(|| MendoError::NotFound(query.to_string()).into()),
+        None => {
+            let media = request::search_media(cfg, query, MediaType::Manga, &[], client)?
+                .data
+                .map(|media_resp| media_resp.media)
+                .ok_or_else(|| MendoError::NotFound(query.to_string()))?;
+            util::append_local_data(&local_media_data, query, media.media_id)?;
+            Ok(media)
+        }
+    }
+}
+
+// Without a token there is no list to look into, which is not an error here
+fn list_entry(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    media_id: i32,
+    client: &Client,
+) -> Result<Option<MediaList>> {
+    if !cfg.access_token_is_valid() {
+        debug!("No token, skipping the list entry");
+        return Ok(None);
+    }
+    let user_id = util::get_user_id(cfg, data_dir, client)?;
+    util::find_media_list(
+        cfg,
+        user_id,
+        media_id,
+        Some(MediaListStatus::Dropped),
+        client,
+    )
+}
+
+// `1989-08-25`, or less when Anilist only knows the year or the month
+fn fuzzy_date(date: &FuzzyDate) -> Option<String> {
+    match (date.year, date.month, date.day) {
+        (Some(year), Some(month), Some(day)) => {
+            Some(format!("{:04}-{:02}-{:02}", year, month, day))
+        }
+        (Some(year), Some(month), None) => Some(format!("{:04}-{:02}", year, month)),
+        (Some(year), None, _) => Some(format!("{:04}", year)),
+        _ => None,
+    }
+}
+
+fn or_unknown(count: Option<i32>) -> String {
+    count.map_or_else(|| "?".to_string(), |count| count.to_string())
+}
+
+pub fn info(cfg: &mut MendoConfig, data_dir: &Path, query: &str, client: &Client) -> Result<()> {
+    let media = resolve(cfg, data_dir, query, client)?;
+    let entry = list_entry(cfg, data_dir, media.media_id, client)?;
+
+    let mut text = format!("{} (id {}", media_title(&media), media.media_id);
+    if let Some(id_mal) = media.id_mal {
+        text.push_str(&format!(", MyAnimeList {}", id_mal));
+    }
+    text.push(')');
+    let titles = [
+        ("Romaji", media.title.romaji.as_deref()),
+        ("English", media.title.english.as_deref()),
+        ("Native", Some(media.title.native.as_str())),
+    ];
+    for (language, title) in titles.iter() {
+        if let Some(title) = title {
+            text.push_str(&format!("\n{}: {}", language, title));
+        }
+    }
+    if !media.synonyms.is_empty() {
+        text.push_str(&format!("\nSynonyms: {}", media.synonyms.join(", ")));
+    }
+    text.push_str(&format!(
+        "\nStatus: {:?}, {} chapters, {} volumes",
+        media.status,
+        or_unknown(media.chapters),
+        or_unknown(media.volumes)
+    ));
+    if let Some(format) = media.format {
+        text.push_str(&format!("\nFormat: {:?}", format));
+    }
+    if let Some(country) = &media.country_of_origin {
+        text.push_str(&format!("\nCountry: {}", country));
+    }
+    if let Some(start_date) = media.start_date.as_ref().and_then(fuzzy_date) {
+        text.push_str(&format!("\nStarted: {}", start_date));
+    }
+    if let Some(site_url) = &media.site_url {
+        text.push_str(&format!("\n{}", site_url));
+    }
+    match &entry {
+        Some(entry) => {
+            text.push_str(&format!(
+                "\nOn your list: {:?}, chapter {}",
+                entry.status, entry.progress
+            ));
+            if let Some(volumes) = entry.progress_volumes.filter(|volumes| *volumes > 0) {
+                text.push_str(&format!(", volume {}", volumes));
+            }
+            if let Some(score) = entry.score.filter(|score| *score > 0.0) {
+                text.push_str(&format!(", score {}", score));
+            }
+        }
+        None if cfg.access_token_is_valid() => text.push_str("\nNot on your list"),
+        None => (),
+    }
+
+    output::emit(
+        &text,
+        Record {
+            event: "info",
+            media_id: Some(media.media_id),
+            title: Some(media_title(&media).to_string()),
+            url: media.site_url.clone(),
+            list_entry: entry,
+            media: Some(media),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
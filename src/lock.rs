@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use fs2::FileExt;
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Held for as long as a mendo instance talks to the trackers, released on drop
+pub struct Lock(File);
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.unlock() {
+            warn!("Could not release the lock: {}", e);
+        }
+    }
+}
+
+// Waits for other instances to finish, up to `timeout`
+pub fn acquire(data_dir: &Path, timeout: Duration) -> Result<Lock> {
+    let lock_path = data_dir.join("mendo.lock");
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(&lock_path)?;
+    let start = Instant::now();
+    loop {
+        match file.try_lock_exclusive() {
+            Ok(()) => {
+                debug!("Acquired {}", lock_path.display());
+                return Ok(Lock(file));
+            }
+            Err(_) if start.elapsed() < timeout => thread::sleep(Duration::from_millis(100)),
+            Err(e) => {
+                error!("Could not lock {}: {}", lock_path.display(), e);
+                return Err(anyhow!(
+                    "Another mendo is still running after {}s. Try again later or raise `lock_timeout`",
+                    timeout.as_secs()
+                ));
+            }
+        }
+    }
+}
+
+// An `update` waiting for the lock. Whoever gets the lock first applies every queued
+// update, so quick clicks on the same title add up instead of overwriting each other.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QueuedUpdate {
+    pub id: String,
+    pub filename: String,
+    pub regexp: String,
+}
+
+impl QueuedUpdate {
+    pub fn new(filename: &str, regexp: &str) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos());
+        QueuedUpdate {
+            id: format!("{}-{}", process::id(), nanos),
+            filename: filename.to_string(),
+            regexp: regexp.to_string(),
+        }
+    }
+}
+
+// The queue file is only locked for the few writes below, never while updating
+fn open_queue(data_dir: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .truncate(false)
+        .open(data_dir.join("update_queue.jsonl"))?;
+    file.lock_exclusive()?;
+    Ok(file)
+}
+
+pub fn push(data_dir: &Path, update: &QueuedUpdate) -> Result<()> {
+    let mut file = open_queue(data_dir)?;
+    file.seek(SeekFrom::End(0))?;
+    writeln!(file, "{}", serde_json::to_string(update)?)?;
+    file.unlock()?;
+    Ok(())
+}
+
+fn read_queue(file: &File) -> Result<Vec<QueuedUpdate>> {
+    let mut updates = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        match serde_json::from_str(&line) {
+            Ok(update) => updates.push(update),
+            Err(e) => warn!("Dropping unreadable queued update `{}`: {}", line, e),
+        }
+    }
+    Ok(updates)
+}
+
+fn write_queue(file: &mut File, updates: &[QueuedUpdate]) -> Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    for update in updates {
+        writeln!(file, "{}", serde_json::to_string(update)?)?;
+    }
+    Ok(())
+}
+
+// Takes every queued update out of the queue
+pub fn drain(data_dir: &Path) -> Result<Vec<QueuedUpdate>> {
+    let mut file = open_queue(data_dir)?;
+    let updates = read_queue(&file)?;
+    write_queue(&mut file, &[])?;
+    file.unlock()?;
+    Ok(updates)
+}
+
+// Takes back an update that will not be applied by this instance after all.
+// Returns false when it is gone already, i.e. another instance is applying it.
+pub fn remove(data_dir: &Path, id: &str) -> Result<bool> {
+    let mut file = open_queue(data_dir)?;
+    let mut updates = read_queue(&file)?;
+    let queued = updates.len();
+    updates.retain(|update| update.id != id);
+    let removed = updates.len() < queued;
+    if removed {
+        write_queue(&mut file, &updates)?;
+    }
+    file.unlock()?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_only_its_own_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let own = QueuedUpdate::new("Berserk c012.cbz", ".*");
+        let other = QueuedUpdate {
+            id: "other".to_string(),
+            ..QueuedUpdate::new("Berserk c013.cbz", ".*")
+        };
+        push(dir.path(), &own).unwrap();
+        push(dir.path(), &other).unwrap();
+
+        assert!(remove(dir.path(), &own.id).unwrap());
+        assert!(!remove(dir.path(), &own.id).unwrap());
+        let queued = drain(dir.path()).unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].id, "other");
+        assert!(drain(dir.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn times_out_while_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        let _held = acquire(dir.path(), Duration::from_secs(0)).unwrap();
+        // fs2 locks are per open file, so a second open conflicts like another process would
+        assert!(acquire(dir.path(), Duration::from_millis(200)).is_err());
+    }
+}
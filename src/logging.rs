@@ -0,0 +1,258 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Local, Utc};
+use fern::colors::{Color, ColoredLevelConfig};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::output;
+use crate::redact::Redactor;
+use crate::PROGRAM_NAME;
+
+// Log target of records that only go to mendo.log, because they are on the terminal already
+pub const FILE_ONLY: &str = "mendo::file_only";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    Stderr,
+    Stdout,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    // Colored only on a terminal and when NO_COLOR is not set
+    Auto,
+    Always,
+    Never,
+}
+
+// Lives in the `logging` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub target: LogTarget,
+    pub color: ColorChoice,
+    // Level of every target not listed in `levels`
+    pub level: String,
+    pub levels: BTreeMap<String, String>,
+    // Rotates mendo.log once it grows past this many bytes, 0 turns it off
+    pub max_size: u64,
+    // Rotates mendo.log when it was last written on another day
+    pub daily: bool,
+    // Number of rotated files kept next to mendo.log
+    pub keep: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        let mut levels = BTreeMap::new();
+        levels.insert(PROGRAM_NAME.to_string(), "warn".to_string());
+        LoggingConfig {
+            target: LogTarget::Stderr,
+            color: ColorChoice::Auto,
+            level: "info".to_string(),
+            levels,
+            max_size: 1024 * 1024,
+            daily: false,
+            keep: 5,
+        }
+    }
+}
+
+pub fn parse_level(level: &str) -> Result<LevelFilter> {
+    level
+        .parse()
+        .map_err(|_| anyhow!("Unknown log level `{}` in the logging config", level))
+}
+
+// Every `-v` makes each configured level one step more verbose
+fn raise(level: LevelFilter, verbosity: u64) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::Off,
+        LevelFilter::Error,
+        LevelFilter::Warn,
+        LevelFilter::Info,
+        LevelFilter::Debug,
+        LevelFilter::Trace,
+    ];
+    let index = LEVELS
+        .iter()
+        .position(|l| *l == level)
+        .expect("Safe because every level is listed");
+    LEVELS[(index + verbosity as usize).min(LEVELS.len() - 1)]
+}
+
+fn use_color(cfg: &LoggingConfig) -> bool {
+    match cfg.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            let stream = match cfg.target {
+                LogTarget::Stdout if !output::is_json() => atty::Stream::Stdout,
+                _ => atty::Stream::Stderr,
+            };
+            env::var_os("NO_COLOR").is_none() && atty::is(stream)
+        }
+    }
+}
+
+// Shifts mendo.log to mendo.log.1, mendo.log.1 to mendo.log.2... and drops the oldest
+fn rotate(log_file_path: &Path, cfg: &LoggingConfig) -> Result<()> {
+    let metadata = match fs::metadata(log_file_path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(()),
+    };
+    let too_big = cfg.max_size > 0 && metadata.len() >= cfg.max_size;
+    let too_old = cfg.daily
+        && matches!(metadata.modified(),
+            Ok(modified) if DateTime::<Local>::from(modified).date() != Local::today());
+    if !too_big && !too_old {
+        return Ok(());
+    }
+
+    // Several mendo may start at once, the one that moves mendo.log away first rotates
+    // and the others just open the new file
+    let claimed = log_file_path.with_extension("log.rotating");
+    match fs::rename(log_file_path, &claimed) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        result => result?,
+    }
+    let rotated = |i: usize| log_file_path.with_extension(format!("log.{}", i));
+    if cfg.keep == 0 {
+        fs::remove_file(&claimed)?;
+        return Ok(());
+    }
+    ignore_missing(fs::remove_file(rotated(cfg.keep)))?;
+    for i in (1..cfg.keep).rev() {
+        ignore_missing(fs::rename(rotated(i), rotated(i + 1)))?;
+    }
+    fs::rename(&claimed, rotated(1))?;
+    Ok(())
+}
+
+// Older rotated files may be missing, e.g. when `keep` was raised
+fn ignore_missing(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+// `secrets` are the credentials from the config file, they never reach the log
+pub fn setup_logging(
+    verbosity: u64,
+    log_file_path: &Path,
+    cfg: &LoggingConfig,
+    secrets: Vec<String>,
+) -> Result<()> {
+    let colors_line = ColoredLevelConfig::new()
+        .error(Color::Red)
+        .warn(Color::Yellow)
+        .info(Color::Green)
+        .debug(Color::Blue)
+        .trace(Color::BrightBlack); // this is the same as the background color
+
+    let mut base_config = fern::Dispatch::new().level(raise(parse_level(&cfg.level)?, verbosity));
+    for (target, level) in &cfg.levels {
+        base_config = base_config.level_for(target.clone(), raise(parse_level(level)?, verbosity));
+    }
+
+    let redactor = Redactor::new(secrets);
+    let file_redactor = redactor.clone();
+    rotate(log_file_path, cfg)?;
+    // Separate file config so we can include year, month and day (UTC format) in file logs.
+    // Files are read with less and grep, so they never get color codes.
+    let file_config = fern::Dispatch::new()
+        .format(move |out, message, record| {
+            out.finish(format_args!(
+                "{date} {level} {target} > {message}",
+                date = Utc::now().format("%Y-%m-%dT%H:%M:%SUTC"),
+                level = record.level(),
+                target = record.target(),
+                message = file_redactor.redact(&message.to_string()),
+            ))
+        })
+        .chain(fern::log_file(log_file_path)?);
+
+    // For terminal output we will just output local %H:%M:%S
+    let color = use_color(cfg);
+    let terminal_config = fern::Dispatch::new()
+        .filter(|metadata| metadata.target() != FILE_ONLY)
+        .format(move |out, message, record| {
+            let message = redactor.redact(&message.to_string()).into_owned();
+            if color {
+                out.finish(format_args!(
+                    "{date} {colored_level} {colored_target} > {colored_message}",
+                    date = Local::now().format("%H:%M:%S"),
+                    colored_level = format_args!(
+                        "\x1B[{}m{}\x1B[0m",
+                        colors_line.get_color(&record.level()).to_fg_str(),
+                        record.level()
+                    ),
+                    colored_target = format_args!("\x1B[95m{}\x1B[0m", record.target()),
+                    colored_message = format_args!(
+                        "\x1B[{}m{}\x1B[0m",
+                        colors_line.get_color(&record.level()).to_fg_str(),
+                        message
+                    ),
+                ))
+            } else {
+                out.finish(format_args!(
+                    "{date} {level} {target} > {message}",
+                    date = Local::now().format("%H:%M:%S"),
+                    level = record.level(),
+                    target = record.target(),
+                    message = message,
+                ))
+            }
+        });
+    // JSON results own stdout, whatever the config says
+    let terminal_config = match cfg.target {
+        LogTarget::Stdout if !output::is_json() => terminal_config.chain(io::stdout()),
+        _ => terminal_config.chain(io::stderr()),
+    };
+
+    base_config
+        .chain(file_config)
+        .chain(terminal_config)
+        .apply()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_shifts_files_and_drops_the_oldest() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("mendo.log");
+        let rotated = |i: usize| log.with_extension(format!("log.{}", i));
+        fs::write(&log, "current").unwrap();
+        fs::write(rotated(1), "first").unwrap();
+        // A gap, as left by a smaller `keep`
+        fs::write(rotated(3), "oldest").unwrap();
+        let cfg = LoggingConfig {
+            max_size: 1,
+            keep: 3,
+            ..LoggingConfig::default()
+        };
+
+        rotate(&log, &cfg).unwrap();
+        assert!(!log.exists());
+        assert_eq!(fs::read_to_string(rotated(1)).unwrap(), "current");
+        assert_eq!(fs::read_to_string(rotated(2)).unwrap(), "first");
+        assert!(!rotated(3).exists());
+        assert!(!log.with_extension("log.rotating").exists());
+        // Nothing left to rotate, as when another instance was faster
+        rotate(&log, &cfg).unwrap();
+        assert_eq!(fs::read_to_string(rotated(1)).unwrap(), "current");
+    }
+}
@@ -1,99 +1,43 @@
 use anyhow::Result;
-use clap::{crate_authors, crate_description, crate_version, App, AppSettings, Arg};
-use fs2::FileExt;
+use clap::{crate_authors, crate_description, crate_version, App, AppSettings, Arg, ArgMatches};
 use reqwest::blocking::Client;
 use std::{
-    fs::{remove_file, File},
+    fs::File,
     io,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
 };
 
-use chrono::{Local, Utc};
-use fern::colors::{Color, ColoredLevelConfig};
-use log::{debug, info, LevelFilter};
+use log::{debug, info};
 
+mod account;
 mod anilist;
+mod comic_info;
+mod config;
+mod edit;
+mod error;
+mod export;
+mod history;
+mod import;
+mod info;
+mod lock;
+mod logging;
+mod output;
+mod progress;
+mod redact;
+mod report;
+mod search;
+mod series;
+mod tracker;
+mod update;
 mod util;
-use anilist::{oauth, request};
+use output::Record;
 use util::MendoConfig;
 
 pub const PROGRAM_NAME: &str = "mendo";
 
-fn setup_logging(verbosity: u64) -> Result<()> {
-    let colors_line = ColoredLevelConfig::new()
-        .error(Color::Red)
-        .warn(Color::Yellow)
-        .info(Color::Green)
-        .debug(Color::Blue)
-        .trace(Color::BrightBlack); // this is the same as the background color
-
-    let mut base_config = fern::Dispatch::new();
-
-    base_config = match verbosity {
-        0 => base_config
-            .level(LevelFilter::Info)
-            .level_for(PROGRAM_NAME, LevelFilter::Warn),
-        1 => base_config
-            .level(LevelFilter::Info)
-            .level_for(PROGRAM_NAME, LevelFilter::Info),
-        2 => base_config
-            .level(LevelFilter::Info)
-            .level_for(PROGRAM_NAME, LevelFilter::Debug),
-        _3_or_more => base_config.level(LevelFilter::Trace),
-    };
-
-    // Separate file config so we can include year, month and day (UTC format) in file logs
-    let log_file_path =
-        util::get_data_dir("", "", PROGRAM_NAME)?.join(format!("{}.log", PROGRAM_NAME));
-    let file_config = fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "{date} {colored_level} {colored_target} > {colored_message}",
-                date = Utc::now().format("%Y-%m-%dT%H:%M:%SUTC"),
-                colored_level = format_args!(
-                    "\x1B[{}m{}\x1B[0m",
-                    colors_line.get_color(&record.level()).to_fg_str(),
-                    record.level()
-                ),
-                colored_target = format_args!("\x1B[95m{}\x1B[0m", record.target()),
-                colored_message = format_args!(
-                    "\x1B[{}m{}\x1B[0m",
-                    colors_line.get_color(&record.level()).to_fg_str(),
-                    message
-                ),
-            ))
-        })
-        .chain(fern::log_file(log_file_path)?);
-
-    // For stdout output we will just output local %H:%M:%S
-    let stdout_config = fern::Dispatch::new()
-        .format(move |out, message, record| {
-            out.finish(format_args!(
-                "{date} {colored_level} {colored_target} > {colored_message}",
-                date = Local::now().format("%H:%M:%S"),
-                colored_level = format_args!(
-                    "\x1B[{}m{}\x1B[0m",
-                    colors_line.get_color(&record.level()).to_fg_str(),
-                    record.level()
-                ),
-                colored_target = format_args!("\x1B[95m{}\x1B[0m", record.target()),
-                colored_message = format_args!(
-                    "\x1B[{}m{}\x1B[0m",
-                    colors_line.get_color(&record.level()).to_fg_str(),
-                    message
-                ),
-            ))
-        })
-        .chain(io::stdout());
-
-    base_config
-        .chain(file_config)
-        .chain(stdout_config)
-        .apply()?;
-
-    Ok(())
-}
-
-fn main() -> Result<()> {
+fn main() {
     let matches = App::new(PROGRAM_NAME)
         .setting(AppSettings::DisableHelpSubcommand)
         .version(crate_version!())
@@ -107,6 +51,15 @@ fn main() -> Result<()> {
                         .long("force")
                         .help("force reauthorize flag"),
                 )
+                .arg(
+                    Arg::with_name("tracker")
+                        .short("t")
+                        .long("tracker")
+                        .help("the tracker to authorize")
+                        .takes_value(true)
+                        .possible_values(&["anilist", "mal", "kitsu"])
+                        .default_value("anilist"),
+                )
                 .about("Authorizes mendo to update progress"),
         )
         .subcommand(
@@ -124,9 +77,363 @@ fn main() -> Result<()> {
                         .long("regexp")
                         .help("Overrides filename regex pattern")
                         .takes_value(true)
-                        .default_value(r"^(.*) (v?|c?)\d+"),
+                        .default_value(
+                            r"^(.*) (?:[vc]?\d+|(?i:extra|omake|oneshot|one-shot|special|bonus))",
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("info")
+                .about("Shows what a title maps to on Anilist")
+                .arg(
+                    Arg::with_name("title")
+                        .help("the title as in the archive filename, or an Anilist id")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("search")
+                .about("Searches Anilist and adds a result to the list as Planning")
+                .arg(
+                    Arg::with_name("query")
+                        .help("the title to search for")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .help("only shows this format")
+                        .takes_value(true)
+                        .possible_values(&["manga", "novel", "one_shot"]),
+                )
+                .arg(
+                    Arg::with_name("status")
+                        .long("status")
+                        .help("only shows this publishing status")
+                        .takes_value(true)
+                        .possible_values(&[
+                            "finished",
+                            "releasing",
+                            "not_yet_released",
+                            "cancelled",
+                            "hiatus",
+                        ]),
+                )
+                .arg(
+                    Arg::with_name("country")
+                        .long("country")
+                        .help("only shows this country of origin, e.g. JP, KR or CN")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("from-year")
+                        .long("from-year")
+                        .help("only shows titles started in or after this year")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("to-year")
+                        .long("to-year")
+                        .help("only shows titles started in or before this year")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("genre")
+                        .long("genre")
+                        .help("only shows titles with one of these genres")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("adult")
+                        .long("adult")
+                        .help("includes adult titles"),
+                )
+                .arg(
+                    Arg::with_name("page")
+                        .long("page")
+                        .help("the page of results")
+                        .takes_value(true)
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::with_name("add")
+                        .long("add")
+                        .help("adds this result to the list as Planning without asking")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("rate")
+                .about("Sets the score of a manga")
+                .arg(
+                    Arg::with_name("title")
+                        .help("the title of the manga")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("score")
+                        .help("the score in your Anilist score format, 0 removes it")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("notes")
+                .about("Edits the notes of a manga with $EDITOR")
+                .arg(
+                    Arg::with_name("title")
+                        .help("the title of the manga")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("private")
+                .about("Toggles whether a manga is private")
+                .arg(
+                    Arg::with_name("title")
+                        .help("the title of the manga")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .help("sets the state instead of toggling it")
+                        .takes_value(true)
+                        .possible_values(&["on", "off"]),
+                ),
+        )
+        .subcommand(
+            App::new("hide")
+                .about("Toggles whether a manga is hidden from status lists")
+                .arg(
+                    Arg::with_name("title")
+                        .help("the title of the manga")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("state")
+                        .help("sets the state instead of toggling it")
+                        .takes_value(true)
+                        .possible_values(&["on", "off"]),
+                ),
+        )
+        .subcommand(
+            App::new("export")
+                .about("Exports the manga list")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .help("the format of the exported list")
+                        .takes_value(true)
+                        .possible_values(&["mal-xml", "json", "csv"])
+                        .default_value("mal-xml"),
+                )
+                .arg(
+                    Arg::with_name("file")
+                        .short("f")
+                        .long("file")
+                        .help("writes the list to this file instead of stdout")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            App::new("import")
+                .about("Imports a MyAnimeList export or a csv list")
+                .arg(
+                    Arg::with_name("file")
+                        .help("the .xml export of MyAnimeList or a .csv of title,progress,status")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .help("Only shows the changes without updating Anilist"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .short("y")
+                        .long("yes")
+                        .help("Applies the changes without asking"),
+                )
+                .arg(
+                    Arg::with_name("delay")
+                        .long("delay")
+                        .help("seconds to wait between two updates")
+                        .takes_value(true)
+                        .default_value("2"),
                 ),
         )
+        .subcommand(
+            App::new("history")
+                .about("Imports reading progress from other readers")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .arg(
+                    Arg::with_name("dry-run")
+                        .short("n")
+                        .long("dry-run")
+                        .global(true)
+                        .help("Only shows the changes without updating Anilist"),
+                )
+                .subcommand(
+                    App::new("tachiyomi")
+                        .about("Reads a Tachiyomi/Mihon .proto.gz backup")
+                        .arg(
+                            Arg::with_name("backup")
+                                .help("the path of the backup file")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    App::new("komga")
+                        .about("Reads read books from a Komga server")
+                        .arg(
+                            Arg::with_name("url")
+                                .long("url")
+                                .help("the base URL of the Komga server")
+                                .takes_value(true)
+                                .default_value("http://localhost:25600"),
+                        )
+                        .arg(
+                            Arg::with_name("user")
+                                .short("u")
+                                .long("user")
+                                .help("the email of the Komga user")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("password")
+                                .short("p")
+                                .long("password")
+                                .help("the password of the Komga user")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    App::new("kavita")
+                        .about("Reads read chapters from a Kavita server")
+                        .arg(
+                            Arg::with_name("url")
+                                .long("url")
+                                .help("the base URL of the Kavita server")
+                                .takes_value(true)
+                                .default_value("http://localhost:5000"),
+                        )
+                        .arg(
+                            Arg::with_name("api-key")
+                                .short("k")
+                                .long("api-key")
+                                .help("the API key of the Kavita user")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    App::new("yacreader")
+                        .about("Reads a YACReader library")
+                        .arg(
+                            Arg::with_name("library")
+                                .help("the path of the library or its library.ydb")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            App::new("logout")
+                .arg(
+                    Arg::with_name("tracker")
+                        .short("t")
+                        .long("tracker")
+                        .help("only log out of this tracker")
+                        .takes_value(true)
+                        .possible_values(&["anilist", "mal", "kitsu"]),
+                )
+                .about("Removes the tracker tokens and the cached account data"),
+        )
+        .subcommand(
+            App::new("whoami").about("Shows the Anilist account of the token and the rate limit"),
+        )
+        .subcommand(
+            App::new("config")
+                .about("Shows, changes and checks the config file")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(App::new("path").about("Prints the path of the config file"))
+                .subcommand(App::new("show").about("Prints the config file without its secrets"))
+                .subcommand(
+                    App::new("get").about("Prints a single setting").arg(
+                        Arg::with_name("key")
+                            .help("the setting, e.g. `notifications.timeout`")
+                            .takes_value(true)
+                            .required(true),
+                    ),
+                )
+                .subcommand(
+                    App::new("set")
+                        .about("Changes a single setting")
+                        .arg(
+                            Arg::with_name("key")
+                                .help("the setting, e.g. `notifications.timeout`")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("value")
+                                .help("the new value, read as YAML")
+                                .takes_value(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(App::new("edit").about("Opens the config file with $EDITOR"))
+                .subcommand(
+                    App::new("check")
+                        .about("Validates the config file and its credentials against Anilist"),
+                ),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .help("reads the config file at this path")
+                .takes_value(true)
+                .env("MENDO_CONFIG")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("data-dir")
+                .long("data-dir")
+                .help("keeps the data of mendo in this directory")
+                .takes_value(true)
+                .env("MENDO_DATA_DIR")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .help("uses the account and settings of this profile")
+                .takes_value(true)
+                .env("MENDO_PROFILE")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .help("prints results as text or as one JSON object per line")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
         .arg(
             Arg::with_name("verbose")
                 .short("v")
@@ -136,68 +443,336 @@ fn main() -> Result<()> {
         )
         .get_matches();
 
+    output::set_json(matches.value_of("output") == Some("json"));
+    // Readers launch `update` without a terminal, so its errors are shown as notifications too
+    let notify = matches.subcommand_name() == Some("update");
+    if let Err(e) = run(&matches) {
+        report::report_error(&e, notify);
+        process::exit(report::exit_code(&e));
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
     let verbosity: u64 = matches.occurrences_of("verbose");
-    let data_dir = util::get_data_dir("", "", PROGRAM_NAME)?;
+    let data_dir = match matches.value_of("data-dir") {
+        Some(data_dir) => PathBuf::from(data_dir),
+        None => util::get_data_dir("", "", PROGRAM_NAME)?,
+    };
+    let config_path = match matches.value_of("config") {
+        Some(config_path) => PathBuf::from(config_path),
+        None => config::default_path()?,
+    };
+    let profile = match matches.value_of("profile") {
+        Some(profile) => Some(profile.to_string()),
+        None => config::default_profile(&config_path),
+    };
+    let (config_path, data_dir) = match profile {
+        // `default` is the main config file itself
+        Some(profile) if profile != "default" => {
+            config::profile_paths(&config_path, &data_dir, &profile)?
+        }
+        _ => (config_path, data_dir),
+    };
 
     util::create_data_dir(&data_dir)?;
-    setup_logging(verbosity)?;
-    let log_file_path =
-        util::get_data_dir("", "", PROGRAM_NAME)?.join(format!("{}.log", PROGRAM_NAME));
-    let log_file = File::open(log_file_path)?;
-    log_file.lock_exclusive()?;
-    debug!("-----Logger is initialized. Starting main program!-----");
-
-    let conf_file = util::get_conf_dir("", "", PROGRAM_NAME)?;
-    while !conf_file.exists() {
-        util::create_proj_conf("", "", PROGRAM_NAME)?;
+    // These also work when the config file no longer loads
+    match matches
+        .subcommand_matches("config")
+        .and_then(|config_matches| config_matches.subcommand_name())
+    {
+        Some("path") => return config::print_path(&config_path),
+        Some("edit") => return config::edit(&config_path, &data_dir),
+        _ => (),
     }
 
-    let mut mendo_cfg: MendoConfig = confy::load(PROGRAM_NAME, None)?;
+    // The logger is configured from the config file, so it is loaded first
+    let mut mendo_cfg: MendoConfig = config::load(&config_path, &data_dir)?;
+    report::set_error_notifications(mendo_cfg.notifications.errors);
+    let log_file_path = data_dir.join(format!("{}.log", PROGRAM_NAME));
+    logging::setup_logging(
+        verbosity,
+        &log_file_path,
+        &mendo_cfg.logging,
+        mendo_cfg.secrets(),
+    )?;
+    debug!("-----Logger is initialized. Starting main program!-----");
+    // The lock timeout is part of the config, so loading it (with its migration) and
+    // rotating the log come first. Both only ever rename complete files into place.
+    // `update` queues itself before waiting, so it takes the lock on its own
+    let _lock = match matches.subcommand_name() {
+        Some("update") => None,
+        _ => Some(lock::acquire(
+            &data_dir,
+            Duration::from_secs(mendo_cfg.lock_timeout),
+        )?),
+    };
     if let Some(auth_matches) = matches.subcommand_matches("auth") {
-        if auth_matches.is_present("force") {
-            debug!("Force flag is present. Deleting user.yml and token in config file...");
-            remove_file(data_dir.join("user.yml"))?;
-            debug!("user.yml file in data directory has been deleted.");
-            confy::store(PROGRAM_NAME, None, MendoConfig::default())?;
-            debug!("Config file has been overridden with default values.");
-        } else if !mendo_cfg.access_token_is_valid() {
-            debug!("Token is invalid...");
+        let tracker = tracker::from_name(
+            auth_matches
+                .value_of("tracker")
+                .expect("Safe because of default value"),
+            &data_dir,
+        )?;
+        if tracker.name() == "anilist" {
+            if auth_matches.is_present("force") {
+                debug!("Force flag is present. Deleting user.yml and token in config file...");
+                account::forget_anilist(&mut mendo_cfg)?;
+                debug!("Anilist token and user.yml have been removed.");
+            } else if !mendo_cfg.access_token_is_valid() {
+                debug!("Token is invalid...");
+            }
         }
-        println!("Starting authorization process...");
-        let res_token = oauth::auth(&mut mendo_cfg)?;
-        util::cfg_save_token(PROGRAM_NAME, &mut mendo_cfg, &res_token)?;
-        println!("Authorization process finished. Now you can use `update` subcommand!");
+        output::say("Starting authorization process...");
+        let client = Client::new();
+        tracker.authorize(&mut mendo_cfg, &client)?;
+        output::emit(
+            "Authorization process finished. Now you can use `update` subcommand!",
+            Record {
+                event: "authorized",
+                tracker: Some(tracker.name().to_string()),
+                ..Record::default()
+            },
+        );
     }
 
     if let Some(update_matches) = matches.subcommand_matches("update") {
         info!("Token from config file is valid. Let's get to work!");
         // Reuse a single client to take advantage of keep-alive connection pooling
         let client = Client::new();
+        // --regexp wins over `parser.regexp`, which wins over the default pattern
+        let regexp = match &mendo_cfg.parser.regexp {
+            Some(regexp) if update_matches.occurrences_of("regexp") == 0 => regexp.clone(),
+            _ => update_matches
+                .value_of("regexp")
+                .expect("Safe because of default value")
+                .to_string(),
+        };
+        update::update(
+            &mut mendo_cfg,
+            &data_dir,
+            update_matches
+                .value_of("filename")
+                .expect("Safe because of clap handling"),
+            &regexp,
+            &client,
+        )?;
+    }
 
-        let filename_pattern = update_matches
-            .value_of("regexp")
-            .expect("Safe because of default value");
-        let user_id = util::get_user_id(&mut mendo_cfg, &data_dir, &client)?;
-        let filename = update_matches
-            .value_of("filename")
-            .expect("Safe because of clap handling");
-        let media_id = util::get_media_id(
+    if let Some(logout_matches) = matches.subcommand_matches("logout") {
+        account::logout(
             &mut mendo_cfg,
             &data_dir,
-            &filename,
-            &filename_pattern,
+            logout_matches.value_of("tracker"),
+        )?;
+    }
+
+    if matches.subcommand_matches("whoami").is_some() {
+        account::whoami(&mendo_cfg, &data_dir, &Client::new())?;
+    }
+
+    if let Some(config_matches) = matches.subcommand_matches("config") {
+        match config_matches.subcommand() {
+            ("show", _) => config::show(&mendo_cfg)?,
+            ("get", Some(get_matches)) => config::get(
+                &mendo_cfg,
+                get_matches
+                    .value_of("key")
+                    .expect("Safe because of clap handling"),
+            )?,
+            ("set", Some(set_matches)) => config::set(
+                &mut mendo_cfg,
+                set_matches
+                    .value_of("key")
+                    .expect("Safe because of clap handling"),
+                set_matches
+                    .value_of("value")
+                    .expect("Safe because of clap handling"),
+            )?,
+            ("check", _) => config::check(&mendo_cfg, &Client::new())?,
+            _ => xkcd_unreachable::xkcd_unreachable!(),
+        }
+    }
+
+    if let Some(rate_matches) = matches.subcommand_matches("rate") {
+        let client = Client::new();
+        edit::rate(
+            &mut mendo_cfg,
+            &data_dir,
+            rate_matches
+                .value_of("title")
+                .expect("Safe because of clap handling"),
+            rate_matches
+                .value_of("score")
+                .expect("Safe because of clap handling"),
             &client,
         )?;
-        let (entry_id, progress) =
-            util::get_eid_and_progress(&mut mendo_cfg, user_id, media_id, &client)?;
+    }
+
+    if let Some(info_matches) = matches.subcommand_matches("info") {
+        info::info(
+            &mut mendo_cfg,
+            &data_dir,
+            info_matches
+                .value_of("title")
+                .expect("Safe because of clap handling"),
+            &Client::new(),
+        )?;
+    }
 
-        request::update_media(&mut mendo_cfg, entry_id, progress + 1, &client)?;
+    if let Some(search_matches) = matches.subcommand_matches("search") {
+        let filters = anilist::model::SearchFilters {
+            format: search_matches
+                .value_of("format")
+                .map(search::parse_filter)
+                .transpose()?,
+            status: search_matches
+                .value_of("status")
+                .map(search::parse_filter)
+                .transpose()?,
+            country: search_matches
+                .value_of("country")
+                .map(|country| country.to_uppercase()),
+            from_year: search_matches
+                .value_of("from-year")
+                .map(str::parse)
+                .transpose()?,
+            to_year: search_matches
+                .value_of("to-year")
+                .map(str::parse)
+                .transpose()?,
+            genres: search_matches
+                .values_of("genre")
+                .map_or_else(Vec::new, |genres| genres.map(str::to_string).collect()),
+            adult: search_matches.is_present("adult"),
+        };
+        search::search(
+            &mut mendo_cfg,
+            search_matches
+                .value_of("query")
+                .expect("Safe because of clap handling"),
+            &filters,
+            search_matches
+                .value_of("page")
+                .expect("Safe because of default value")
+                .parse()?,
+            search_matches.value_of("add").map(str::parse).transpose()?,
+            &Client::new(),
+        )?;
+    }
 
-        #[cfg(target_family = "unix")]
-        util::notify_updated(&filename, &filename_pattern, progress + 1)?;
+    if let Some(notes_matches) = matches.subcommand_matches("notes") {
+        let client = Client::new();
+        edit::edit_notes(
+            &mut mendo_cfg,
+            &data_dir,
+            notes_matches
+                .value_of("title")
+                .expect("Safe because of clap handling"),
+            &client,
+        )?;
+    }
+
+    if let Some(private_matches) = matches.subcommand_matches("private") {
+        let client = Client::new();
+        edit::set_private(
+            &mut mendo_cfg,
+            &data_dir,
+            private_matches
+                .value_of("title")
+                .expect("Safe because of clap handling"),
+            private_matches.value_of("state").map(|state| state == "on"),
+            &client,
+        )?;
+    }
+
+    if let Some(hide_matches) = matches.subcommand_matches("hide") {
+        let client = Client::new();
+        edit::set_hidden(
+            &mut mendo_cfg,
+            &data_dir,
+            hide_matches
+                .value_of("title")
+                .expect("Safe because of clap handling"),
+            hide_matches.value_of("state").map(|state| state == "on"),
+            &client,
+        )?;
+    }
+
+    if let Some(export_matches) = matches.subcommand_matches("export") {
+        let client = Client::new();
+        let format = export_matches
+            .value_of("format")
+            .expect("Safe because of default value");
+        let file = export_matches.value_of("file");
+        let mut out: Box<dyn io::Write> = match file {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+        let count = export::export(&mut mendo_cfg, &data_dir, format, &mut out, &client)?;
+        // Without a file the list itself is the output
+        if let Some(path) = file {
+            output::emit(
+                &format!("Exported {} entries to {}", count, path),
+                Record {
+                    event: "exported",
+                    count: Some(count),
+                    message: Some(path.to_string()),
+                    ..Record::default()
+                },
+            );
+        }
+    }
+
+    if let Some(import_matches) = matches.subcommand_matches("import") {
+        let client = Client::new();
+        let delay: u64 = import_matches
+            .value_of("delay")
+            .expect("Safe because of default value")
+            .parse()?;
+        import::import(
+            &mut mendo_cfg,
+            &data_dir,
+            Path::new(
+                import_matches
+                    .value_of("file")
+                    .expect("Safe because of clap handling"),
+            ),
+            import_matches.is_present("dry-run"),
+            import_matches.is_present("yes"),
+            Duration::from_secs(delay),
+            &client,
+        )?;
+    }
+
+    if let Some(history_matches) = matches.subcommand_matches("history") {
+        let client = Client::new();
+        let dry_run = history_matches.is_present("dry-run");
+        let progress = match history_matches.subcommand() {
+            ("tachiyomi", Some(m)) => history::tachiyomi::read_backup(Path::new(
+                m.value_of("backup").expect("Safe because of clap handling"),
+            ))?,
+            ("komga", Some(m)) => history::komga::read_progress(
+                m.value_of("url").expect("Safe because of default value"),
+                m.value_of("user").expect("Safe because of clap handling"),
+                m.value_of("password")
+                    .expect("Safe because of clap handling"),
+                &client,
+            )?,
+            ("kavita", Some(m)) => history::kavita::read_progress(
+                m.value_of("url").expect("Safe because of default value"),
+                m.value_of("api-key")
+                    .expect("Safe because of clap handling"),
+                &client,
+            )?,
+            ("yacreader", Some(m)) => history::yacreader::read_library(Path::new(
+                m.value_of("library")
+                    .expect("Safe because of clap handling"),
+            ))?,
+            _ => xkcd_unreachable::xkcd_unreachable!(),
+        };
+        history::apply_progress(&mut mendo_cfg, &data_dir, progress, dry_run, &client)?;
     }
 
     debug!("-----Everything is finished!-----");
-    log_file.unlock()?;
     Ok(())
 }
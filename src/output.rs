@@ -0,0 +1,122 @@
+use serde::Serialize;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::anilist::model::{Media, MediaList, MediaListStatus};
+
+// Set once from `--output` before any subcommand runs
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+// A single result of a subcommand, printed as one JSON object per line with `--output json`.
+// The field names are relied upon by scripts, so only ever add new ones.
+#[derive(Serialize, Debug, Default)]
+pub struct Record {
+    pub event: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_progress: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_progress: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_volumes: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_volumes: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_status: Option<MediaListStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_status: Option<MediaListStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hidden: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tracker: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dry_run: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ok: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit_remaining: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media: Option<Media>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list_entry: Option<MediaList>,
+}
+
+// Prints `text` for humans or `record` for scripts
+pub fn emit(text: &str, record: Record) {
+    if is_json() {
+        match serde_json::to_string(&record) {
+            Ok(line) => println!("{}", line),
+            Err(e) => eprintln!("Could not serialize {:?}: {}", record, e),
+        }
+    } else {
+        println!("{}", text);
+    }
+}
+
+// Messages without a result, e.g. "Opening browser...", stay off stdout in JSON mode
+pub fn say(text: &str) {
+    if is_json() {
+        eprintln!("{}", text);
+    } else {
+        println!("{}", text);
+    }
+}
+
+// Like `say`, prompts stay off stdout in JSON mode. Answers are read from stdin.
+fn prompt(message: &str) -> io::Result<()> {
+    if is_json() {
+        eprint!("{}", message);
+        io::stderr().flush()
+    } else {
+        print!("{}", message);
+        io::stdout().flush()
+    }
+}
+
+pub fn ask(message: &str) -> io::Result<String> {
+    prompt(message)?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+// Like `ask`, but the answer is not echoed to the terminal
+pub fn ask_secret(message: &str) -> io::Result<String> {
+    prompt(message)?;
+    rpassword::read_password()
+}
@@ -0,0 +1,511 @@
+use anyhow::Result;
+use log::{debug, info};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::anilist::model::MediaFormat;
+use crate::comic_info::ComicInfo;
+
+// A chapter as named by the archive, before any policy is applied
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chapter {
+    Number(i32),
+    Decimal(f64),
+    Range(f64, f64),
+    // Extras, omake, oneshots... which have no place in the numbering
+    Special(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DecimalPolicy {
+    // 12.5 counts as chapter 12
+    Floor,
+    Skip,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RangePolicy {
+    // 10-12 counts as chapter 12
+    Upper,
+    Lower,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SpecialPolicy {
+    Skip,
+    // Adds one chapter, like every archive did before chapters were parsed
+    Increment,
+}
+
+// Lives in the `chapter_policy` section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct ChapterPolicy {
+    pub decimal: DecimalPolicy,
+    pub range: RangePolicy,
+    pub special: SpecialPolicy,
+}
+
+impl Default for ChapterPolicy {
+    fn default() -> Self {
+        ChapterPolicy {
+            decimal: DecimalPolicy::Floor,
+            range: RangePolicy::Upper,
+            special: SpecialPolicy::Skip,
+        }
+    }
+}
+
+// What reading an archive means for the list entry
+#[derive(Debug, Clone, PartialEq)]
+pub enum Read {
+    // Used when the archive does not name a chapter at all
+    NextChapter,
+    // Several of the above, combined from queued updates
+    NextChapters(i32),
+    Chapter(i32),
+    Skip(String),
+    // `last_chapter` comes from the volume table, if the volume is listed there
+    Volume {
+        volume: i32,
+        last_chapter: Option<i32>,
+    },
+}
+
+// The values every tracker is updated to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub chapters: i32,
+    pub volumes: Option<i32>,
+    // Set when the series auto-completes and the last chapter is read
+    pub completed: bool,
+}
+
+impl Read {
+    // Progress is never decreased, re-reading an older chapter or volume keeps the entry as is
+    pub fn apply(&self, chapters: i32, volumes: i32) -> Progress {
+        match self {
+            Read::NextChapter => Progress {
+                chapters: chapters + 1,
+                volumes: None,
+                completed: false,
+            },
+            Read::NextChapters(count) => Progress {
+                chapters: chapters + count,
+                volumes: None,
+                completed: false,
+            },
+            Read::Chapter(chapter) => Progress {
+                chapters: (*chapter).max(chapters),
+                volumes: None,
+                completed: false,
+            },
+            Read::Skip(_) => Progress {
+                chapters,
+                volumes: None,
+                completed: false,
+            },
+            Read::Volume {
+                volume,
+                last_chapter,
+            } => Progress {
+                chapters: last_chapter.map_or(chapters, |last| last.max(chapters)),
+                volumes: Some((*volume).max(volumes)),
+                completed: false,
+            },
+        }
+    }
+
+    // Merges two reads of the same title into one update. Reads that cannot be merged,
+    // e.g. a chapter number and a volume, are applied one after the other instead.
+    pub fn combine(&self, other: &Read) -> Option<Read> {
+        let count = |read: &Read| match read {
+            Read::NextChapter => Some(1),
+            Read::NextChapters(count) => Some(*count),
+            _ => None,
+        };
+        match (self, other) {
+            (Read::Skip(_), read) | (read, Read::Skip(_)) => Some(read.clone()),
+            (Read::Chapter(a), Read::Chapter(b)) => Some(Read::Chapter(*a.max(b))),
+            (
+                Read::Volume {
+                    volume: a,
+                    last_chapter: a_last,
+                },
+                Read::Volume {
+                    volume: b,
+                    last_chapter: b_last,
+                },
+            ) => Some(Read::Volume {
+                volume: *a.max(b),
+                last_chapter: (*a_last).max(*b_last),
+            }),
+            (a, b) => match (count(a), count(b)) {
+                (Some(a), Some(b)) => Some(Read::NextChapters(a + b)),
+                _ => None,
+            },
+        }
+    }
+
+    // The offset only shifts chapters named in the filename
+    pub fn with_offset(self, offset: i32) -> Read {
+        match self {
+            Read::Chapter(chapter) => Read::Chapter(chapter + offset),
+            read => read,
+        }
+    }
+}
+
+// A volume archive names a volume (`v05`, `Vol.5`, `Volume 5`) but no chapter
+pub fn parse_volume(filename: &str, name: &str) -> Option<i32> {
+    let rest = filename.strip_prefix(name).unwrap_or(filename);
+    let volume_re =
+        Regex::new(r"(?i)(?:^|[\s_\-\[(])(?:v|vol\.?|volume)\s?(\d+)").expect("Valid regex");
+    let chapter_re =
+        Regex::new(r"(?i)(?:^|[\s_\-\[(])(?:c|ch\.?|chapter)\s?\d+").expect("Valid regex");
+    if chapter_re.is_match(rest) {
+        return None;
+    }
+    volume_re
+        .captures(rest)
+        .and_then(|caps| caps.get(1))
+        .and_then(|m| m.as_str().parse().ok())
+}
+
+const SPECIAL_WORDS: &str = "extra|omake|oneshot|one-shot|one shot|special|bonus";
+
+// Separators between the title and the chapter, and the volume when the chapter is
+// named as well, as in `v03 c012`
+const CHAPTER_PREFIX: &str = r"(?i)^[\s_\-\[(]*(?:(?:v|vol\.?|volume)\s?\d+[\s_\-]*(?:c|ch\.?|chapter)|(?:c|ch\.?|chapter)?)\s?";
+
+// Only the number right after the title counts, so years and group tags further down
+// the filename are not mistaken for chapters
+pub fn parse_chapter(filename: &str, name: &str) -> Option<Chapter> {
+    let rest = filename.strip_prefix(name).unwrap_or(filename);
+    let range_re = Regex::new(&format!(
+        r"{}(\d+(?:\.\d+)?)\s?-\s?(c|ch\.?)?(\d+(?:\.\d+)?)",
+        CHAPTER_PREFIX
+    ))
+    .expect("Valid regex");
+    let number_re =
+        Regex::new(&format!(r"{}(\d+(?:\.\d+)?)", CHAPTER_PREFIX)).expect("Valid regex");
+    let special_re = Regex::new(&format!(r"(?i)\b({})\b", SPECIAL_WORDS)).expect("Valid regex");
+
+    if let Some(caps) = range_re.captures(rest) {
+        let (from, to): (f64, f64) = (caps[1].parse().ok()?, caps[3].parse().ok()?);
+        // Both ends have to look like chapters: `c012 - 2019` is chapter 12 of a 2019
+        // release, so a bare upper end that is longer than the lower one is not a chapter
+        let digits = |number: &str| number.split('.').next().map_or(0, str::len);
+        let like_chapter =
+            caps.get(2).is_some() || digits(&caps[3]) < 4 || digits(&caps[3]) <= digits(&caps[1]);
+        if to > from && like_chapter {
+            return Some(Chapter::Range(from, to));
+        }
+    }
+    if let Some(caps) = number_re.captures(rest) {
+        return Some(Chapter::from_number(caps[1].parse().ok()?));
+    }
+    special_re
+        .captures(rest)
+        .map(|caps| Chapter::Special(caps[1].to_string()))
+}
+
+impl Chapter {
+    // `.0` is still a whole chapter
+    pub fn from_number(chapter: f64) -> Chapter {
+        if chapter.fract() == 0.0 {
+            Chapter::Number(chapter as i32)
+        } else {
+            Chapter::Decimal(chapter)
+        }
+    }
+
+    // Anilist only knows whole chapters, so every chapter ends up as an integer or is skipped
+    pub fn to_read(&self, policy: &ChapterPolicy) -> Read {
+        match self {
+            Chapter::Number(chapter) => Read::Chapter(*chapter),
+            Chapter::Decimal(chapter) => match policy.decimal {
+                DecimalPolicy::Floor => Read::Chapter(chapter.floor() as i32),
+                DecimalPolicy::Skip => {
+                    Read::Skip(format!("chapter {} is a decimal chapter", chapter))
+                }
+            },
+            Chapter::Range(from, to) => match policy.range {
+                RangePolicy::Upper => Read::Chapter(to.floor() as i32),
+                RangePolicy::Lower => Read::Chapter(from.floor() as i32),
+            },
+            Chapter::Special(kind) => match policy.special {
+                SpecialPolicy::Skip => Read::Skip(format!("`{}` is not a numbered chapter", kind)),
+                SpecialPolicy::Increment => Read::NextChapter,
+            },
+        }
+    }
+}
+
+// `volume_chapters.yml` maps a title (as read from the filename) to the last
+// chapter of each of its volumes, e.g.
+//
+// Yotsubato!:
+//   1: 7
+//   2: 14
+pub struct VolumeTable(HashMap<String, BTreeMap<i32, i32>>);
+
+impl VolumeTable {
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join("volume_chapters.yml");
+        if !path.exists() {
+            debug!("No volume table at {}", path.display());
+            return Ok(VolumeTable(HashMap::new()));
+        }
+        let table = serde_yaml::from_str(&fs::read_to_string(&path)?)?;
+        Ok(VolumeTable(table))
+    }
+
+    pub fn last_chapter(&self, title: &str, volume: i32) -> Option<i32> {
+        self.0
+            .get(title)
+            .and_then(|volumes| volumes.get(&volume))
+            .copied()
+    }
+}
+
+fn read_volume(name: &str, volume: i32, data_dir: &Path) -> Result<Read> {
+    Ok(Read::Volume {
+        volume,
+        last_chapter: VolumeTable::load(data_dir)?.last_chapter(name, volume),
+    })
+}
+
+// `Number` is the chapter and, like in filenames, `Volume` only counts when there is
+// none. Without either, nothing is returned and the filename is parsed instead.
+pub fn read_from_comic_info(
+    comic_info: &ComicInfo,
+    name: &str,
+    format: MediaFormat,
+    policy: &ChapterPolicy,
+    data_dir: &Path,
+) -> Result<Option<Read>> {
+    let number = comic_info
+        .number
+        .as_deref()
+        .and_then(|number| parse_chapter(number, ""));
+    let read = match (format, number, comic_info.volume) {
+        (MediaFormat::Novel, _, Some(volume))
+        | (MediaFormat::Novel, Some(Chapter::Number(volume)), None)
+        | (_, None, Some(volume)) => read_volume(name, volume, data_dir)?,
+        (MediaFormat::Novel, _, None) => return Ok(None),
+        (_, Some(chapter), _) => chapter.to_read(policy),
+        (_, None, None) => return Ok(None),
+    };
+    info!("ComicInfo.xml of `{}` is counted as {:?}", name, read);
+    Ok(Some(read))
+}
+
+// Novels are read a volume at a time, so any number after the title is a volume
+fn read_novel(filename: &str, name: &str, policy: &ChapterPolicy, data_dir: &Path) -> Result<Read> {
+    let read = match parse_volume(filename, name) {
+        Some(volume) => Read::Chapter(volume),
+        None => match parse_chapter(filename, name) {
+            Some(Chapter::Special(kind)) => {
+                Read::Skip(format!("`{}` is not a numbered volume", kind))
+            }
+            Some(number) => number.to_read(policy),
+            None => Read::Skip(format!("`{}` does not name a volume", filename)),
+        },
+    };
+    match read {
+        Read::Chapter(volume) => {
+            info!(
+                "`{}` is volume {} of the novel `{}`",
+                filename, volume, name
+            );
+            read_volume(name, volume, data_dir)
+        }
+        read => Ok(read),
+    }
+}
+
+pub fn read_from_filename(
+    filename: &str,
+    name: &str,
+    format: MediaFormat,
+    policy: &ChapterPolicy,
+    data_dir: &Path,
+) -> Result<Read> {
+    if format == MediaFormat::Novel {
+        return read_novel(filename, name, policy, data_dir);
+    }
+    if let Some(volume) = parse_volume(filename, name) {
+        let last_chapter = VolumeTable::load(data_dir)?.last_chapter(name, volume);
+        debug!(
+            "`{}` is volume {} of `{}`, which ends with chapter {:?}",
+            filename, volume, name, last_chapter
+        );
+        return Ok(Read::Volume {
+            volume,
+            last_chapter,
+        });
+    }
+
+    match parse_chapter(filename, name) {
+        Some(chapter) => {
+            let read = chapter.to_read(policy);
+            info!("`{}` is {:?}, counted as {:?}", filename, chapter, read);
+            Ok(read)
+        }
+        None => {
+            debug!(
+                "Could not find a chapter in `{}`, adding one chapter",
+                filename
+            );
+            Ok(Read::NextChapter)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(filename: &str) -> Option<Chapter> {
+        parse_chapter(filename, "Berserk")
+    }
+
+    fn read(filename: &str, policy: &ChapterPolicy) -> Read {
+        let dir = tempfile::tempdir().unwrap();
+        read_from_filename(filename, "Berserk", MediaFormat::Manga, policy, dir.path()).unwrap()
+    }
+
+    #[test]
+    fn parses_whole_and_decimal_chapters() {
+        assert_eq!(chapter("Berserk c012.cbz"), Some(Chapter::Number(12)));
+        assert_eq!(
+            chapter("Berserk - Ch. 12 [Group].cbz"),
+            Some(Chapter::Number(12))
+        );
+        assert_eq!(chapter("Berserk 012.5.cbz"), Some(Chapter::Decimal(12.5)));
+        // `.0` is still a whole chapter
+        assert_eq!(chapter("Berserk c012.0.cbz"), Some(Chapter::Number(12)));
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(
+            chapter("Berserk c010-012.cbz"),
+            Some(Chapter::Range(10.0, 12.0))
+        );
+        assert_eq!(
+            chapter("Berserk ch.10 - ch.12 [Group].cbz"),
+            Some(Chapter::Range(10.0, 12.0))
+        );
+        assert_eq!(
+            chapter("Berserk c98-102.cbz"),
+            Some(Chapter::Range(98.0, 102.0))
+        );
+        assert_eq!(
+            chapter("Berserk c999-c1000.cbz"),
+            Some(Chapter::Range(999.0, 1000.0))
+        );
+    }
+
+    #[test]
+    fn year_after_chapter_is_not_a_range() {
+        assert_eq!(
+            chapter("Berserk c012 - 2019 (Digital).cbz"),
+            Some(Chapter::Number(12))
+        );
+        assert_eq!(
+            chapter("Berserk 012.5 - 2019 (Digital).cbz"),
+            Some(Chapter::Decimal(12.5))
+        );
+        // Backwards is not a range either
+        assert_eq!(chapter("Berserk c012-3.cbz"), Some(Chapter::Number(12)));
+    }
+
+    #[test]
+    fn parses_specials() {
+        assert_eq!(
+            chapter("Berserk Extra [Group].cbz"),
+            Some(Chapter::Special("Extra".to_string()))
+        );
+        assert_eq!(
+            chapter("Berserk - Omake.cbz"),
+            Some(Chapter::Special("Omake".to_string()))
+        );
+        assert_eq!(chapter("Berserk.cbz"), None);
+    }
+
+    #[test]
+    fn applies_chapter_policy() {
+        let default = ChapterPolicy::default();
+        let other = ChapterPolicy {
+            decimal: DecimalPolicy::Skip,
+            range: RangePolicy::Lower,
+            special: SpecialPolicy::Increment,
+        };
+        assert_eq!(read("Berserk c012.5.cbz", &default), Read::Chapter(12));
+        assert!(matches!(read("Berserk c012.5.cbz", &other), Read::Skip(_)));
+        assert_eq!(read("Berserk c010-012.cbz", &default), Read::Chapter(12));
+        assert_eq!(read("Berserk c010-012.cbz", &other), Read::Chapter(10));
+        assert!(matches!(read("Berserk Extra.cbz", &default), Read::Skip(_)));
+        assert_eq!(read("Berserk Extra.cbz", &other), Read::NextChapter);
+        assert_eq!(read("Berserk.cbz", &default), Read::NextChapter);
+    }
+
+    #[test]
+    fn tells_volumes_from_chapters() {
+        let policy = ChapterPolicy::default();
+        assert_eq!(
+            read("Berserk v03.cbz", &policy),
+            Read::Volume {
+                volume: 3,
+                last_chapter: None
+            }
+        );
+        assert_eq!(
+            read("Berserk Vol.3 [Group].cbz", &policy),
+            Read::Volume {
+                volume: 3,
+                last_chapter: None
+            }
+        );
+        // A chapter wins over the volume it is in
+        assert_eq!(read("Berserk v03 c012.cbz", &policy), Read::Chapter(12));
+        assert_eq!(
+            read("Berserk Vol.3 Ch.10-12.cbz", &policy),
+            Read::Chapter(12)
+        );
+        assert_eq!(parse_volume("Berserk v03 c012.cbz", "Berserk"), None);
+    }
+
+    #[test]
+    fn volume_table_gives_last_chapter() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("volume_chapters.yml"),
+            "Berserk:\n  1: 7\n  2: 14\n",
+        )
+        .unwrap();
+        let read = read_from_filename(
+            "Berserk v02.cbz",
+            "Berserk",
+            MediaFormat::Manga,
+            &ChapterPolicy::default(),
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(
+            read,
+            Read::Volume {
+                volume: 2,
+                last_chapter: Some(14)
+            }
+        );
+    }
+}
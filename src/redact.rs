@@ -0,0 +1,140 @@
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+
+const REDACTED: &str = "[REDACTED]";
+
+// Names of the values that are credentials, wherever they are logged
+const KEYS: &str =
+    "access_token|refresh_token|id_token|client_secret|secret|token|code|code_verifier|password";
+
+// Scrubs credentials from log lines. Known secrets from the config file are replaced
+// wherever they show up, everything that looks like a token, a secret, an authorization
+// code or a password is replaced by its key. Keys only count in the forms they are
+// logged in, so `status code: 404` stays as it is.
+#[derive(Clone)]
+pub struct Redactor {
+    bearer_re: Regex,
+    key_value_res: Vec<Regex>,
+    secrets: Vec<String>,
+}
+
+impl Redactor {
+    pub fn new(secrets: Vec<String>) -> Self {
+        let key_value_res = [
+            // Debug output and JSON bodies, which always quote the value:
+            // `token: "..."` and `"token": "..."`
+            format!(r#"(?i)("?\b(?:{})"?\s*:\s*")[^"]+"#, KEYS),
+            // Query strings and form bodies: `?code=...&state=...`
+            format!(r"(?i)((?:^|[?&])(?:{})=)[^&\s]+", KEYS),
+            // YAML, with the key at the start of the line: `  token: ...`
+            format!(r"(?im)(^[ \t]*(?:{}):[ \t]+)\S.*$", KEYS),
+        ]
+        .iter()
+        .map(|pattern| Regex::new(pattern).expect("Valid regex"))
+        .collect();
+        Redactor {
+            bearer_re: Regex::new(r"(?i)\b(bearer\s+)[\w\-.~+/=]+").expect("Valid regex"),
+            key_value_res,
+            secrets: secrets.into_iter().filter(|s| s.len() >= 8).collect(),
+        }
+    }
+
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for secret in &self.secrets {
+            if text.contains(secret.as_str()) {
+                text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+            }
+        }
+        for re in std::iter::once(&self.bearer_re).chain(&self.key_value_res) {
+            if re.is_match(&text) {
+                text = Cow::Owned(
+                    re.replace_all(&text, |caps: &Captures| format!("{}{}", &caps[1], REDACTED))
+                        .into_owned(),
+                );
+            }
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redact(text: &str) -> String {
+        Redactor::new(vec!["known-secret-value".to_string(), "short".to_string()])
+            .redact(text)
+            .into_owned()
+    }
+
+    #[test]
+    fn redacts_debug_output() {
+        assert_eq!(
+            redact(r#"MalToken { access_token: "abc.def", refresh_token: "ghi" }"#),
+            r#"MalToken { access_token: "[REDACTED]", refresh_token: "[REDACTED]" }"#
+        );
+    }
+
+    #[test]
+    fn redacts_json_bodies() {
+        assert_eq!(
+            redact(r#"{"grant_type":"password","username":"me","password":"hunter22"}"#),
+            r#"{"grant_type":"password","username":"me","password":"[REDACTED]"}"#
+        );
+        assert_eq!(
+            redact(r#"{"client_id": "123", "client_secret": "s3cr3t", "code": "def"}"#),
+            r#"{"client_id": "123", "client_secret": "[REDACTED]", "code": "[REDACTED]"}"#
+        );
+    }
+
+    #[test]
+    fn redacts_query_strings() {
+        assert_eq!(
+            redact("GET /callback?code=abc123&state=xyz HTTP/1.1"),
+            "GET /callback?code=[REDACTED]&state=xyz HTTP/1.1"
+        );
+        assert_eq!(
+            redact("grant_type=refresh_token&refresh_token=abc&client_id=1"),
+            "grant_type=refresh_token&refresh_token=[REDACTED]&client_id=1"
+        );
+    }
+
+    #[test]
+    fn redacts_yaml() {
+        assert_eq!(
+            redact("id: 1234\ntoken: eyJabc.def\nmal:\n  password: hunter22 # old"),
+            "id: 1234\ntoken: [REDACTED]\nmal:\n  password: [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn redacts_bearer_and_known_secrets() {
+        assert_eq!(
+            redact("Authorization: Bearer eyJabc.def-ghi"),
+            "Authorization: Bearer [REDACTED]"
+        );
+        assert_eq!(
+            redact("Sending known-secret-value along"),
+            "Sending [REDACTED] along"
+        );
+        // Too short to replace safely wherever it shows up
+        assert_eq!(redact("short stories"), "short stories");
+    }
+
+    #[test]
+    fn leaves_ordinary_text_alone() {
+        for text in &[
+            "Kavita returned code `404'",
+            "Anilist returned status code: 404",
+            "exit code=3",
+            "token count: 3",
+            "Found 3 codes in the tokens: 12",
+            "Searching for `Secret Garden`",
+            r#"Media { status_code: "FINISHED" }"#,
+            "Anilist returned code `401'. Unauthorized!",
+        ] {
+            assert_eq!(redact(text), *text);
+        }
+    }
+}
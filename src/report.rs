@@ -0,0 +1,99 @@
+use anyhow::Error;
+use log::error;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::{MendoError, NETWORK_EXIT_CODE};
+use crate::logging::FILE_ONLY;
+use crate::output::{self, Record};
+
+#[cfg(target_family = "unix")]
+use crate::util;
+
+// Set from `notifications.errors` once the config file is loaded, errors before that
+// are always notified
+static ERROR_NOTIFICATIONS: AtomicBool = AtomicBool::new(true);
+
+pub fn set_error_notifications(enabled: bool) {
+    ERROR_NOTIFICATIONS.store(enabled, Ordering::Relaxed);
+}
+
+// Every error of `mendo` ends up here, so no failure goes unnoticed when there is no terminal
+pub fn report_error(err: &Error, notify: bool) {
+    // Printed here so it shows even when logging is not set up yet or turned off
+    error!(target: FILE_ONLY, "{:#}", err);
+    eprintln!("Error: {:#}", err);
+    let hint = hint(err);
+    if let Some(hint) = hint {
+        eprintln!("Hint: {}", hint);
+    }
+    // Scripts get the error on stdout too, next to the other results
+    if output::is_json() {
+        output::emit(
+            "",
+            Record {
+                event: "error",
+                message: Some(format!("{:#}", err)),
+                hint: hint.map(str::to_string),
+                exit_code: Some(exit_code(err)),
+                ..Record::default()
+            },
+        );
+    }
+
+    #[cfg(target_family = "unix")]
+    if notify && ERROR_NOTIFICATIONS.load(Ordering::Relaxed) {
+        let body = match hint {
+            Some(hint) => format!("{}\n{}", err, hint),
+            None => err.to_string(),
+        };
+        if let Err(e) = util::notify_error("mendo could not update your progress", &body) {
+            error!("Could not show the error notification: {}", e);
+        }
+    }
+    #[cfg(not(target_family = "unix"))]
+    let _ = notify;
+}
+
+// A short next step for the errors users can fix themselves
+fn hint(err: &Error) -> Option<&'static str> {
+    if let Some(e) = err.downcast_ref::<MendoError>() {
+        return match e {
+            MendoError::Unauthorized => Some("Run `mendo auth` to authorize again."),
+            MendoError::TrackerUnauthorized(_) => {
+                Some("Run `mendo auth --tracker <name>` for the tracker in the error.")
+            }
+            MendoError::RateLimited(_) => {
+                Some("Anilist is rate limiting you, wait a minute and try again.")
+            }
+            MendoError::NotFound(_) => Some(
+                "Add an alias line `<title> - mediaId: <id>` to media_data.txt in the data directory.",
+            ),
+            MendoError::Parse(_) => {
+                Some("Pass a matching --regexp or add a `regexp` for the series in series.yml.")
+            }
+            MendoError::Network(_) => Some("Check your internet connection and try again."),
+            MendoError::Config(_) => Some(
+                "Fix the config file, or delete it to start over with the defaults. The previous file is kept next to it with a .bak extension.",
+            ),
+            MendoError::NoListEntry(_) | MendoError::GraphQL(_) => None,
+        };
+    }
+    if let Some(e) = err.downcast_ref::<reqwest::Error>() {
+        if e.is_connect() || e.is_timeout() {
+            return Some("Check your internet connection and try again.");
+        }
+    }
+    if err.downcast_ref::<regex::Error>().is_some() {
+        return Some("Fix the regex given to --regexp or in series.yml.");
+    }
+    None
+}
+
+pub fn exit_code(err: &Error) -> i32 {
+    match err.downcast_ref::<MendoError>() {
+        Some(e) => e.exit_code(),
+        // Requests made outside of query_graphql, e.g. by the other trackers
+        None if err.downcast_ref::<reqwest::Error>().is_some() => NETWORK_EXIT_CODE,
+        None => 1,
+    }
+}
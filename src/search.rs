@@ -0,0 +1,156 @@
+use anyhow::{anyhow, Result};
+use reqwest::blocking::Client;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::anilist::model::{Media, MediaListStatus, SearchFilters};
+use crate::anilist::request;
+use crate::error::MendoError;
+use crate::export::media_title;
+use crate::output::{self, Record};
+use crate::util::MendoConfig;
+
+const PER_PAGE: i32 = 10;
+
+// `one_shot` on the command line is `ONE_SHOT` in the API
+pub fn parse_filter<T: DeserializeOwned>(value: &str) -> Result<T> {
+    Ok(serde_json::from_value(Value::String(value.to_uppercase()))?)
+}
+
+fn describe(media: &Media) -> String {
+    let mut details = Vec::new();
+    if let Some(format) = media.format {
+        details.push(format!("{:?}", format));
+    }
+    if let Some(country) = &media.country_of_origin {
+        details.push(country.clone());
+    }
+    if let Some(year) = media.start_date.as_ref().and_then(|date| date.year) {
+        details.push(year.to_string());
+    }
+    details.push(format!("{:?}", media.status));
+    if let Some(chapters) = media.chapters {
+        details.push(format!("{} chapters", chapters));
+    }
+    let mut text = format!(
+        "{} (id {}) {}",
+        media_title(media),
+        media.media_id,
+        details.join(", ")
+    );
+    if let Some(entry) = &media.media_list_entry {
+        text.push_str(&format!(" [on your list: {:?}]", entry.status));
+    }
+    text
+}
+
+// Entries that exist already are left alone, whatever their status
+fn add_planning(cfg: &mut MendoConfig, media: &Media, client: &Client) -> Result<()> {
+    if !cfg.access_token_is_valid() {
+        return Err(MendoError::Unauthorized.into());
+    }
+    let title = media_title(media);
+    if let Some(entry) = &media.media_list_entry {
+        output::emit(
+            &format!("`{}` is already on your list as {:?}.", title, entry.status),
+            Record {
+                event: "skipped",
+                media_id: Some(media.media_id),
+                title: Some(title.to_string()),
+                old_status: Some(entry.status.clone()),
+                message: Some("already on the list".to_string()),
+                ..Record::default()
+            },
+        );
+        return Ok(());
+    }
+    request::create_new_entry(cfg, media.media_id, MediaListStatus::Planning, 0, client)?;
+    output::emit(
+        &format!("`{}` is added to your list as Planning.", title),
+        Record {
+            event: "added",
+            media_id: Some(media.media_id),
+            title: Some(title.to_string()),
+            new_status: Some(MediaListStatus::Planning),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+// `add` is the position of the result to add, otherwise a terminal is asked for one
+pub fn search(
+    cfg: &mut MendoConfig,
+    query: &str,
+    filters: &SearchFilters,
+    page: i32,
+    add: Option<usize>,
+    client: &Client,
+) -> Result<()> {
+    let media_page = request::search_media_page(cfg, query, filters, page, PER_PAGE, client)?
+        .data
+        .map(|page_resp| page_resp.page)
+        .ok_or_else(|| MendoError::NotFound(query.to_string()))?;
+    let results = media_page.media;
+    if results.is_empty() {
+        output::say(&format!("No results for `{}`.", query));
+    }
+    for (i, media) in results.iter().enumerate() {
+        output::emit(
+            &format!("{:>2}. {}", i + 1, describe(media)),
+            Record {
+                event: "search_result",
+                media_id: Some(media.media_id),
+                title: Some(media_title(media).to_string()),
+                url: media.site_url.clone(),
+                media: Some(media.clone()),
+                ..Record::default()
+            },
+        );
+    }
+    let page_info = media_page.page_info;
+    if page_info.has_next_page {
+        let unknown = || "?".to_string();
+        output::say(&format!(
+            "Page {} of {} ({} results), `--page {}` shows the next ones.",
+            page_info.current_page,
+            page_info
+                .last_page
+                .map_or_else(unknown, |last| last.to_string()),
+            page_info
+                .total
+                .map_or_else(unknown, |total| total.to_string()),
+            page_info.current_page + 1
+        ));
+    }
+
+    let choice = match add {
+        Some(add) => Some(add),
+        None if !results.is_empty()
+            && cfg.access_token_is_valid()
+            && atty::is(atty::Stream::Stdin) =>
+        {
+            let answer = output::ask(
+                "Add one of them to your list as Planning? Enter its number, or nothing to skip: ",
+            )?;
+            if answer.is_empty() {
+                None
+            } else {
+                Some(
+                    answer
+                        .parse()
+                        .map_err(|_| anyhow!("`{}` is not the number of a result", answer))?,
+                )
+            }
+        }
+        None => None,
+    };
+    if let Some(choice) = choice {
+        let media = choice
+            .checked_sub(1)
+            .and_then(|i| results.get(i))
+            .ok_or_else(|| anyhow!("There is no result number {} on this page", choice))?;
+        add_planning(cfg, media, client)?;
+    }
+    Ok(())
+}
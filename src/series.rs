@@ -0,0 +1,178 @@
+use anyhow::{anyhow, Result};
+use log::{debug, error};
+use regex::Regex;
+use serde::Deserialize;
+use serde_yaml::Mapping;
+use std::fs;
+use std::path::Path;
+
+use crate::anilist::model::Media;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TitleLanguage {
+    Romaji,
+    English,
+    Native,
+}
+
+// Settings of a single series, every field is optional
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SeriesConfig {
+    // Added to the chapter in the filename, e.g. 50 when a second season restarts at 1
+    pub chapter_offset: i32,
+    // Used instead of `--regexp`, an archive matching it always belongs to this series
+    pub regexp: Option<String>,
+    pub ignore: bool,
+    // Marks the entry as completed once the last chapter is read
+    pub auto_complete: bool,
+    pub title_language: Option<TitleLanguage>,
+}
+
+// `series.yml` in the data directory maps an Anilist media id to its settings, e.g.
+//
+// 30013:
+//   chapter_offset: 50
+//   regexp: '^(One Piece) S2 '
+//   auto_complete: true
+//   title_language: english
+//
+// Series are kept in the order of the file, so the first regexp that matches wins.
+#[derive(Default)]
+pub struct SeriesTable(Vec<(i32, SeriesConfig, Option<Regex>)>);
+
+// The title is taken from the first group, like with `--regexp`
+fn compile_regexp(media_id: i32, pattern: &str) -> Result<Regex> {
+    let regexp = Regex::new(pattern).map_err(|e| {
+        anyhow!(
+            "Invalid regexp of series `{}` in series.yml: {}",
+            media_id,
+            e
+        )
+    })?;
+    if regexp.captures_len() < 2 {
+        error!("Regexp of series `{}` has no capture group", media_id);
+        return Err(anyhow!(
+            "The regexp of series `{}` in series.yml needs a group around the title, e.g. '^(One Piece) S2 '",
+            media_id
+        ));
+    }
+    Ok(regexp)
+}
+
+impl SeriesTable {
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join("series.yml");
+        if !path.exists() {
+            debug!("No series settings at {}", path.display());
+            return Ok(SeriesTable::default());
+        }
+        SeriesTable::parse(&fs::read_to_string(&path)?)
+    }
+
+    fn parse(yaml: &str) -> Result<Self> {
+        // A mapping keeps the order of the file, unlike deserializing into a map type
+        let mapping: Mapping = serde_yaml::from_str(yaml)?;
+        let mut table = Vec::new();
+        for (key, value) in mapping {
+            let media_id: i32 = serde_yaml::from_value(key.clone())
+                .map_err(|_| anyhow!("`{:?}` in series.yml is not an Anilist id", key))?;
+            let series: SeriesConfig = serde_yaml::from_value(value).map_err(|e| {
+                anyhow!(
+                    "Invalid settings of series `{}` in series.yml: {}",
+                    media_id,
+                    e
+                )
+            })?;
+            let regexp = match &series.regexp {
+                Some(pattern) => Some(compile_regexp(media_id, pattern)?),
+                None => None,
+            };
+            table.push((media_id, series, regexp));
+        }
+        Ok(SeriesTable(table))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, media_id: i32) -> SeriesConfig {
+        self.0
+            .iter()
+            .find(|(id, _, _)| *id == media_id)
+            .map(|(_, series, _)| series.clone())
+            .unwrap_or_default()
+    }
+
+    // Returns the media id and pattern of the first series whose regexp matches
+    pub fn match_filename(&self, filename: &str) -> Option<(i32, &str)> {
+        self.0.iter().find_map(|(media_id, _, regexp)| {
+            let regexp = regexp.as_ref().filter(|regexp| regexp.is_match(filename))?;
+            debug!("`{}` matches the regexp of series `{}`", filename, media_id);
+            Some((*media_id, regexp.as_str()))
+        })
+    }
+}
+
+// Falls back to whatever title exists when the preferred one is missing
+pub fn preferred_title(media: &Media, language: TitleLanguage) -> &str {
+    let title = &media.title;
+    match language {
+        TitleLanguage::Romaji => title.romaji.as_deref().or(title.english.as_deref()),
+        TitleLanguage::English => title.english.as_deref().or(title.romaji.as_deref()),
+        TitleLanguage::Native => None,
+    }
+    .unwrap_or(&title.native)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_regexp_in_file_order_wins() {
+        let table = SeriesTable::parse(
+            "
+30013:
+  regexp: '^(One Piece) S2 '
+  chapter_offset: 50
+21:
+  regexp: '^(One Piece) '
+30002:
+  ignore: true
+",
+        )
+        .unwrap();
+        // Both regexps match, many times over so a random order would show
+        for _ in 0..20 {
+            assert_eq!(
+                table.match_filename("One Piece S2 c001.cbz"),
+                Some((30013, "^(One Piece) S2 "))
+            );
+        }
+        assert_eq!(
+            table.match_filename("One Piece c1000.cbz"),
+            Some((21, "^(One Piece) "))
+        );
+        assert_eq!(table.match_filename("Berserk c001.cbz"), None);
+        assert_eq!(table.get(30013).chapter_offset, 50);
+        assert!(table.get(30002).ignore);
+        assert!(!table.get(1).ignore);
+    }
+
+    #[test]
+    fn rejects_regexp_without_group() {
+        let err = SeriesTable::parse("30013:\n  regexp: '^One Piece S2 '\n")
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("needs a group"));
+    }
+
+    #[test]
+    fn rejects_invalid_regexp_and_ids() {
+        assert!(SeriesTable::parse("30013:\n  regexp: '^(One Piece'\n").is_err());
+        assert!(SeriesTable::parse("One Piece:\n  ignore: true\n").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Result};
+use log::error;
+use reqwest::blocking::Client;
+use std::path::{Path, PathBuf};
+
+use super::{Tracker, TrackerEntry};
+use crate::anilist::model::MediaFormat;
+use crate::anilist::{oauth, request};
+use crate::progress::Progress;
+use crate::util::{self, MendoConfig};
+
+pub struct Anilist {
+    data_dir: PathBuf,
+}
+
+impl Anilist {
+    pub fn new(data_dir: &Path) -> Self {
+        Anilist {
+            data_dir: data_dir.to_path_buf(),
+        }
+    }
+}
+
+impl Tracker for Anilist {
+    fn name(&self) -> &'static str {
+        "anilist"
+    }
+
+    fn authorize(&self, cfg: &mut MendoConfig, _client: &Client) -> Result<()> {
+        let res_token = oauth::auth(cfg)?;
+        util::cfg_save_token(cfg, &res_token)
+    }
+
+    fn is_authorized(&self, cfg: &MendoConfig) -> bool {
+        cfg.access_token_is_valid()
+    }
+
+    fn forget(&self, cfg: &mut MendoConfig) -> bool {
+        let had_token = cfg.access_token_is_valid();
+        cfg.reset_token();
+        had_token
+    }
+
+    fn search(
+        &self,
+        cfg: &mut MendoConfig,
+        title: &str,
+        format: MediaFormat,
+        client: &Client,
+    ) -> Result<i32> {
+        util::get_media_id_by_name(cfg, &self.data_dir, title, Some(format), client)
+    }
+
+    fn get_entry(
+        &self,
+        cfg: &mut MendoConfig,
+        media_id: i32,
+        client: &Client,
+    ) -> Result<TrackerEntry> {
+        let user_id = util::get_user_id(cfg, &self.data_dir, client)?;
+        // Dropped entries are looked up too, undoing an update must never remove them
+        let (media_list, created) =
+            match util::find_media_list(cfg, user_id, media_id, None, client)? {
+                Some(media_list) => (media_list, false),
+                None => (util::get_media_list(cfg, user_id, media_id, client)?, true),
+            };
+        Ok(TrackerEntry {
+            entry_id: Some(media_list.entry_id),
+            media_id,
+            progress: media_list.progress,
+            progress_volumes: media_list.progress_volumes.unwrap_or(0),
+            status: if created {
+                None
+            } else {
+                Some(media_list.status)
+            },
+            created,
+        })
+    }
+
+    fn update_progress(
+        &self,
+        cfg: &mut MendoConfig,
+        entry: &TrackerEntry,
+        progress: &Progress,
+        client: &Client,
+    ) -> Result<()> {
+        let entry_id = entry
+            .entry_id
+            .expect("Safe because get_entry always returns an entry id");
+        if progress.completed {
+            request::complete_media(cfg, entry_id, progress.chapters, progress.volumes, client)?;
+            return Ok(());
+        }
+        match progress.volumes {
+            Some(volumes) => {
+                request::update_media_volumes(cfg, entry_id, progress.chapters, volumes, client)?
+            }
+            None => request::update_media(cfg, entry_id, progress.chapters, client)?,
+        };
+        Ok(())
+    }
+
+    fn restore(&self, cfg: &mut MendoConfig, entry: &TrackerEntry, client: &Client) -> Result<()> {
+        let entry_id = entry
+            .entry_id
+            .expect("Safe because get_entry always returns an entry id");
+        if entry.created {
+            let deleted = request::delete_media(cfg, entry_id, client)?
+                .data
+                .and_then(|delete_resp| delete_resp.delete_media_list_entry.deleted);
+            if deleted != Some(true) {
+                error!("Anilist did not delete entry `{}`", entry_id);
+                return Err(anyhow!("Anilist did not delete entry `{}`", entry_id));
+            }
+        } else {
+            request::restore_media(
+                cfg,
+                entry_id,
+                entry.status.as_ref(),
+                entry.progress,
+                entry.progress_volumes,
+                client,
+            )?;
+        }
+        Ok(())
+    }
+}
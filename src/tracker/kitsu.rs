@@ -0,0 +1,563 @@
+use anyhow::{anyhow, Result};
+use log::{debug, error, info};
+use reqwest::blocking::{Client, Response};
+use serde::Deserialize;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+use super::{Tracker, TrackerEntry};
+use crate::anilist::model::{MediaFormat, MediaListStatus};
+use crate::config;
+use crate::output;
+use crate::progress::Progress;
+use crate::util::{self, MendoConfig};
+
+const KITSU_TOKEN_URL: &str = "https://kitsu.io/api/oauth/token";
+const KITSU_API_URL: &str = "https://kitsu.io/api/edge";
+const JSON_API: &str = "application/vnd.api+json";
+// Every subtype of Kitsu manga but `novel`, which Anilist calls manga as well
+const MANGA_SUBTYPES: &str = "doujin,manga,manhua,manhwa,oel,oneshot";
+
+#[derive(Deserialize, Debug)]
+struct KitsuToken {
+    access_token: String,
+}
+
+// Kitsu follows JSON:API, where ids are always strings
+#[derive(Deserialize, Debug)]
+struct KitsuResource<A> {
+    id: String,
+    attributes: A,
+}
+
+#[derive(Deserialize, Debug)]
+struct KitsuDocument<A> {
+    data: Vec<KitsuResource<A>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KitsuUser {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct KitsuManga {
+    canonical_title: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct KitsuLibraryEntry {
+    progress: i32,
+    status: String,
+    #[serde(default)]
+    reconsuming: bool,
+}
+
+pub struct Kitsu {
+    data_dir: PathBuf,
+    // Only point somewhere else in tests
+    api_url: String,
+    token_url: String,
+}
+
+impl Kitsu {
+    pub fn new(data_dir: &Path) -> Self {
+        Kitsu {
+            data_dir: data_dir.to_path_buf(),
+            api_url: KITSU_API_URL.to_string(),
+            token_url: KITSU_TOKEN_URL.to_string(),
+        }
+    }
+
+    fn check_status(&self, res: Response) -> Result<Response> {
+        util::check_status(res, "Kitsu", Some(self.name()))
+    }
+}
+
+// Kitsu has no repeating status, rereading is a flag on top of `current`
+fn to_kitsu_status(status: &MediaListStatus) -> (&'static str, bool) {
+    match status {
+        MediaListStatus::Current => ("current", false),
+        MediaListStatus::Repeating => ("current", true),
+        MediaListStatus::Completed => ("completed", false),
+        MediaListStatus::Paused => ("on_hold", false),
+        MediaListStatus::Dropped => ("dropped", false),
+        MediaListStatus::Planning => ("planned", false),
+    }
+}
+
+fn from_kitsu_status(status: &str, reconsuming: bool) -> Option<MediaListStatus> {
+    match status {
+        "current" if reconsuming => Some(MediaListStatus::Repeating),
+        "current" => Some(MediaListStatus::Current),
+        "completed" => Some(MediaListStatus::Completed),
+        "on_hold" => Some(MediaListStatus::Paused),
+        "dropped" => Some(MediaListStatus::Dropped),
+        "planned" => Some(MediaListStatus::Planning),
+        _ => None,
+    }
+}
+
+fn parse_id(id: &str) -> Result<i32> {
+    id.parse()
+        .map_err(|_| anyhow!("Kitsu returned a non numeric id `{}`", id))
+}
+
+impl Tracker for Kitsu {
+    fn name(&self) -> &'static str {
+        "kitsu"
+    }
+
+    // Kitsu does not offer an authorization code flow, only the password grant
+    fn authorize(&self, cfg: &mut MendoConfig, client: &Client) -> Result<()> {
+        let username = output::ask("Kitsu email: ")?;
+        let password = output::ask_secret("Kitsu password: ")?;
+        let res = client
+            .post(&self.token_url)
+            .json(&json!({
+                "grant_type": "password",
+                "username": username,
+                "password": password,
+            }))
+            .send()?;
+        let token: KitsuToken = self.check_status(res)?.json()?;
+        info!("Successfully authenticated the user on Kitsu!");
+
+        let res = client
+            .get(format!("{}/users", self.api_url))
+            .bearer_auth(&token.access_token)
+            .query(&[("filter[self]", "true")])
+            .send()?;
+        let users: KitsuDocument<KitsuUser> = self.check_status(res)?.json()?;
+        let user = users
+            .data
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Kitsu did not return the authenticated user"))?;
+        debug!("Got Kitsu user `{}` ({})", user.attributes.name, user.id);
+
+        cfg.trackers.kitsu.user_id = user.id;
+        cfg.trackers.kitsu.token = token.access_token;
+        config::store(&*cfg)?;
+        info!("Configuration with Kitsu token is saved!");
+        Ok(())
+    }
+
+    fn is_authorized(&self, cfg: &MendoConfig) -> bool {
+        !cfg.trackers.kitsu.token.is_empty() && !cfg.trackers.kitsu.user_id.is_empty()
+    }
+
+    fn forget(&self, cfg: &mut MendoConfig) -> bool {
+        let kitsu = &mut cfg.trackers.kitsu;
+        let had_token = !kitsu.token.is_empty() || !kitsu.user_id.is_empty();
+        kitsu.token.clear();
+        kitsu.user_id.clear();
+        had_token
+    }
+
+    // Novels often have the very title of their manga adaptation, so they are cached apart
+    fn search(
+        &self,
+        cfg: &mut MendoConfig,
+        title: &str,
+        format: MediaFormat,
+        client: &Client,
+    ) -> Result<i32> {
+        let (local_media_data, subtypes) = match format {
+            MediaFormat::Novel => (self.data_dir.join("media_data_kitsu_novel.txt"), "novel"),
+            _ => (self.data_dir.join("media_data_kitsu.txt"), MANGA_SUBTYPES),
+        };
+        if let Some(media_id) = util::find_local_media_id(&local_media_data, title)? {
+            return Ok(media_id);
+        }
+
+        info!("Searching Kitsu {:?} using name: `{}`...", format, title);
+        let res = client
+            .get(format!("{}/manga", self.api_url))
+            .bearer_auth(cfg.trackers.kitsu.token.as_str())
+            .header("Accept", JSON_API)
+            .query(&[
+                ("filter[text]", title),
+                ("filter[subtype]", subtypes),
+                ("page[limit]", "1"),
+            ])
+            .send()?;
+        let result: KitsuDocument<KitsuManga> = self.check_status(res)?.json()?;
+        match result.data.into_iter().next() {
+            Some(manga) => {
+                let media_id = parse_id(&manga.id)?;
+                debug!(
+                    "Found Kitsu id `{}` ({}) for `{}`",
+                    media_id, manga.attributes.canonical_title, title
+                );
+                util::append_local_data(&local_media_data, title, media_id)?;
+                Ok(media_id)
+            }
+            None => {
+                error!("Kitsu did not return any result for `{}`", title);
+                Err(anyhow!(
+                    "Kitsu did not return any result! Maybe recheck your archive filename?"
+                ))
+            }
+        }
+    }
+
+    fn get_entry(
+        &self,
+        cfg: &mut MendoConfig,
+        media_id: i32,
+        client: &Client,
+    ) -> Result<TrackerEntry> {
+        let media_id_str = media_id.to_string();
+        let res = client
+            .get(format!("{}/library-entries", self.api_url))
+            .bearer_auth(cfg.trackers.kitsu.token.as_str())
+            .header("Accept", JSON_API)
+            .query(&[
+                ("filter[userId]", cfg.trackers.kitsu.user_id.as_str()),
+                ("filter[mangaId]", media_id_str.as_str()),
+            ])
+            .send()?;
+        let entries: KitsuDocument<KitsuLibraryEntry> = self.check_status(res)?.json()?;
+        if let Some(entry) = entries.data.into_iter().next() {
+            return Ok(TrackerEntry {
+                entry_id: Some(parse_id(&entry.id)?),
+                media_id,
+                progress: entry.attributes.progress,
+                progress_volumes: 0,
+                status: from_kitsu_status(&entry.attributes.status, entry.attributes.reconsuming),
+                created: false,
+            });
+        }
+
+        debug!("It seems like user has not created entry for this title on Kitsu!");
+        let res = client
+            .post(format!("{}/library-entries", self.api_url))
+            .bearer_auth(cfg.trackers.kitsu.token.as_str())
+            .header("Content-Type", JSON_API)
+            .header("Accept", JSON_API)
+            .body(
+                json!({
+                    "data": {
+                        "type": "libraryEntries",
+                        "attributes": { "status": "current", "progress": 0 },
+                        "relationships": {
+                            "user": { "data": { "type": "users", "id": cfg.trackers.kitsu.user_id } },
+                            "media": { "data": { "type": "manga", "id": media_id_str } },
+                        },
+                    }
+                })
+                .to_string(),
+            )
+            .send()?;
+        let created: serde_json::Value = self.check_status(res)?.json()?;
+        let entry_id = created["data"]["id"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Kitsu did not return the created library entry"))?;
+        Ok(TrackerEntry {
+            entry_id: Some(parse_id(entry_id)?),
+            media_id,
+            progress: 0,
+            progress_volumes: 0,
+            status: None,
+            created: true,
+        })
+    }
+
+    fn update_progress(
+        &self,
+        cfg: &mut MendoConfig,
+        entry: &TrackerEntry,
+        progress: &Progress,
+        client: &Client,
+    ) -> Result<()> {
+        let entry_id = entry
+            .entry_id
+            .expect("Safe because get_entry always returns an entry id");
+        // Kitsu only tracks owned volumes, not read ones
+        info!(
+            "Updating progress of Kitsu library entry `{}` with: progress `{}`...",
+            entry_id, progress.chapters
+        );
+        let mut attributes = json!({ "progress": progress.chapters });
+        if progress.completed {
+            attributes["status"] = json!("completed");
+        }
+        let res = client
+            .patch(format!("{}/library-entries/{}", self.api_url, entry_id))
+            .bearer_auth(cfg.trackers.kitsu.token.as_str())
+            .header("Content-Type", JSON_API)
+            .header("Accept", JSON_API)
+            .body(
+                json!({
+                    "data": {
+                        "type": "libraryEntries",
+                        "id": entry_id.to_string(),
+                        "attributes": attributes,
+                    }
+                })
+                .to_string(),
+            )
+            .send()?;
+        self.check_status(res)?;
+        Ok(())
+    }
+
+    fn restore(&self, cfg: &mut MendoConfig, entry: &TrackerEntry, client: &Client) -> Result<()> {
+        let entry_id = entry
+            .entry_id
+            .expect("Safe because get_entry always returns an entry id");
+        let url = format!("{}/library-entries/{}", self.api_url, entry_id);
+        if entry.created {
+            info!("Removing Kitsu library entry `{}` again...", entry_id);
+            let res = client
+                .delete(&url)
+                .bearer_auth(cfg.trackers.kitsu.token.as_str())
+                .header("Accept", JSON_API)
+                .send()?;
+            self.check_status(res)?;
+            return Ok(());
+        }
+        info!(
+            "Restoring Kitsu library entry `{}` to: status `{:?}`, progress `{}`...",
+            entry_id, entry.status, entry.progress
+        );
+        let mut attributes = json!({ "progress": entry.progress });
+        if let Some(status) = &entry.status {
+            let (status, reconsuming) = to_kitsu_status(status);
+            attributes["status"] = json!(status);
+            attributes["reconsuming"] = json!(reconsuming);
+        }
+        let res = client
+            .patch(&url)
+            .bearer_auth(cfg.trackers.kitsu.token.as_str())
+            .header("Content-Type", JSON_API)
+            .header("Accept", JSON_API)
+            .body(
+                json!({
+                    "data": {
+                        "type": "libraryEntries",
+                        "id": entry_id.to_string(),
+                        "attributes": attributes,
+                    }
+                })
+                .to_string(),
+            )
+            .send()?;
+        self.check_status(res)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::MendoError;
+    use mockito::{mock, Matcher};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn kitsu(dir: &TempDir) -> Kitsu {
+        Kitsu {
+            data_dir: dir.path().to_path_buf(),
+            api_url: format!("{}/kitsu", mockito::server_url()),
+            token_url: format!("{}/kitsu/token", mockito::server_url()),
+        }
+    }
+
+    fn config() -> MendoConfig<'static> {
+        let mut cfg = MendoConfig::default();
+        cfg.trackers.kitsu.user_id = "7".to_string();
+        cfg.trackers.kitsu.token = "token".to_string();
+        cfg
+    }
+
+    fn entry(created: bool) -> TrackerEntry {
+        TrackerEntry {
+            entry_id: Some(55),
+            media_id: 12,
+            progress: 3,
+            progress_volumes: 0,
+            status: if created {
+                None
+            } else {
+                Some(MediaListStatus::Paused)
+            },
+            created,
+        }
+    }
+
+    #[test]
+    fn searches_by_subtype_and_caches_the_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config();
+        let novel = mock("GET", "/kitsu/manga")
+            .match_header("authorization", "Bearer token")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("filter[text]".into(), "Overlord".into()),
+                Matcher::UrlEncoded("filter[subtype]".into(), "novel".into()),
+            ]))
+            .with_body(r#"{"data": [{"id": "10", "attributes": {"canonicalTitle": "Overlord"}}]}"#)
+            .expect(1)
+            .create();
+        let manga = mock("GET", "/kitsu/manga")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("filter[text]".into(), "Overlord".into()),
+                Matcher::UrlEncoded("filter[subtype]".into(), MANGA_SUBTYPES.into()),
+            ]))
+            .with_body(r#"{"data": [{"id": "20", "attributes": {"canonicalTitle": "Overlord"}}]}"#)
+            .expect(1)
+            .create();
+
+        let kitsu = kitsu(&dir);
+        let client = Client::new();
+        for _ in 0..2 {
+            assert_eq!(
+                kitsu
+                    .search(&mut cfg, "Overlord", MediaFormat::Novel, &client)
+                    .unwrap(),
+                10
+            );
+            assert_eq!(
+                kitsu
+                    .search(&mut cfg, "Overlord", MediaFormat::Manga, &client)
+                    .unwrap(),
+                20
+            );
+        }
+        novel.assert();
+        manga.assert();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("media_data_kitsu_novel.txt")).unwrap(),
+            "Overlord - mediaId: 10\n"
+        );
+    }
+
+    #[test]
+    fn gets_an_existing_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config();
+        let _entries = mock("GET", "/kitsu/library-entries")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("filter[userId]".into(), "7".into()),
+                Matcher::UrlEncoded("filter[mangaId]".into(), "12".into()),
+            ]))
+            .with_body(
+                r#"{"data": [{"id": "55", "attributes":
+                    {"progress": 3, "status": "current", "reconsuming": true}}]}"#,
+            )
+            .create();
+        let create = mock("POST", "/kitsu/library-entries").expect(0).create();
+
+        let entry = kitsu(&dir).get_entry(&mut cfg, 12, &Client::new()).unwrap();
+        create.assert();
+        assert_eq!(entry.entry_id, Some(55));
+        assert_eq!(entry.progress, 3);
+        assert_eq!(entry.status, Some(MediaListStatus::Repeating));
+        assert!(!entry.created);
+    }
+
+    #[test]
+    fn creates_a_missing_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config();
+        let _entries = mock("GET", "/kitsu/library-entries")
+            .match_query(Matcher::UrlEncoded("filter[mangaId]".into(), "13".into()))
+            .with_body(r#"{"data": []}"#)
+            .create();
+        let create = mock("POST", "/kitsu/library-entries")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "data": {
+                    "attributes": { "status": "current", "progress": 0 },
+                    "relationships": {
+                        "user": { "data": { "id": "7" } },
+                        "media": { "data": { "id": "13" } },
+                    },
+                }
+            })))
+            .with_status(201)
+            .with_body(r#"{"data": {"id": "56"}}"#)
+            .create();
+
+        let entry = kitsu(&dir).get_entry(&mut cfg, 13, &Client::new()).unwrap();
+        create.assert();
+        assert_eq!(entry.entry_id, Some(56));
+        assert_eq!(entry.progress, 0);
+        assert_eq!(entry.status, None);
+        assert!(entry.created);
+    }
+
+    #[test]
+    fn sends_a_status_only_when_completing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config();
+        let kitsu = kitsu(&dir);
+        let client = Client::new();
+        for (completed, attributes) in &[
+            (false, serde_json::json!({ "progress": 4 })),
+            (
+                true,
+                serde_json::json!({ "progress": 4, "status": "completed" }),
+            ),
+        ] {
+            let update = mock("PATCH", "/kitsu/library-entries/55")
+                .match_body(Matcher::Json(serde_json::json!({
+                    "data": { "type": "libraryEntries", "id": "55", "attributes": attributes }
+                })))
+                .create();
+            let progress = Progress {
+                chapters: 4,
+                volumes: Some(1),
+                completed: *completed,
+            };
+            kitsu
+                .update_progress(&mut cfg, &entry(false), &progress, &client)
+                .unwrap();
+            update.assert();
+        }
+    }
+
+    #[test]
+    fn restores_the_status_or_removes_created_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config();
+        let kitsu = kitsu(&dir);
+        let client = Client::new();
+        let restore = mock("PATCH", "/kitsu/library-entries/55")
+            .match_body(Matcher::PartialJson(serde_json::json!({
+                "data": { "attributes":
+                    { "progress": 3, "status": "on_hold", "reconsuming": false } }
+            })))
+            .create();
+        kitsu.restore(&mut cfg, &entry(false), &client).unwrap();
+        restore.assert();
+
+        let delete = mock("DELETE", "/kitsu/library-entries/55")
+            .with_status(204)
+            .create();
+        kitsu.restore(&mut cfg, &entry(true), &client).unwrap();
+        delete.assert();
+    }
+
+    #[test]
+    fn is_unauthorized_on_401() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config();
+        let _entries = mock("GET", "/kitsu/library-entries")
+            .match_query(Matcher::UrlEncoded("filter[mangaId]".into(), "14".into()))
+            .with_status(401)
+            .create();
+        let e = kitsu(&dir)
+            .get_entry(&mut cfg, 14, &Client::new())
+            .unwrap_err();
+        assert!(
+            matches!(
+                e.downcast_ref::<MendoError>(),
+                Some(MendoError::TrackerUnauthorized("kitsu"))
+            ),
+            "{:#}",
+            e
+        );
+    }
+}
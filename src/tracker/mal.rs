@@ -0,0 +1,656 @@
+use anyhow::{anyhow, Result};
+use log::{debug, error, info};
+use oauth2::CsrfToken;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use super::{Tracker, TrackerEntry};
+use crate::anilist::model::{MediaFormat, MediaListStatus};
+use crate::anilist::oauth;
+use crate::config;
+use crate::error::MendoError;
+use crate::output;
+use crate::progress::Progress;
+use crate::util::{self, MendoConfig};
+
+const MAL_AUTH_URL: &str = "https://myanimelist.net/v1/oauth2/authorize";
+const MAL_TOKEN_URL: &str = "https://myanimelist.net/v1/oauth2/token";
+const MAL_API_URL: &str = "https://api.myanimelist.net/v2";
+// Results to look through for one of the right format, MAL cannot filter the search by it
+const SEARCH_LIMIT: &str = "10";
+
+#[derive(Deserialize, Debug)]
+struct MalToken {
+    access_token: String,
+    refresh_token: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MalNode {
+    id: i32,
+    title: String,
+    #[serde(default)]
+    media_type: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MalSearchItem {
+    node: MalNode,
+}
+
+#[derive(Deserialize, Debug)]
+struct MalSearchResponse {
+    data: Vec<MalSearchItem>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MalListStatus {
+    status: Option<String>,
+    #[serde(default)]
+    is_rereading: bool,
+    num_chapters_read: i32,
+    num_volumes_read: i32,
+}
+
+#[derive(Deserialize, Debug)]
+struct MalManga {
+    my_list_status: Option<MalListStatus>,
+}
+
+pub struct MyAnimeList {
+    data_dir: PathBuf,
+    // Only point somewhere else in tests
+    api_url: String,
+    token_url: String,
+}
+
+impl MyAnimeList {
+    pub fn new(data_dir: &Path) -> Self {
+        MyAnimeList {
+            data_dir: data_dir.to_path_buf(),
+            api_url: MAL_API_URL.to_string(),
+            token_url: MAL_TOKEN_URL.to_string(),
+        }
+    }
+
+    fn save_token(&self, cfg: &mut MendoConfig, token: MalToken) -> Result<()> {
+        cfg.trackers.mal.token = token.access_token;
+        cfg.trackers.mal.refresh_token = token.refresh_token;
+        config::store(&*cfg)?;
+        info!("Configuration with MyAnimeList token is saved!");
+        Ok(())
+    }
+
+    fn refresh_token(&self, cfg: &mut MendoConfig, client: &Client) -> Result<()> {
+        debug!("Refreshing MyAnimeList access token...");
+        let res = client
+            .post(&self.token_url)
+            .form(&[
+                ("client_id", cfg.trackers.mal.client_id.as_str()),
+                ("grant_type", "refresh_token"),
+                ("refresh_token", cfg.trackers.mal.refresh_token.as_str()),
+            ])
+            .send()?;
+        // A refresh token that no longer works needs a new authorization
+        if !res.status().is_success() {
+            error!("MyAnimeList did not refresh the token: {}", res.status());
+            return Err(MendoError::TrackerUnauthorized(self.name()).into());
+        }
+        let token: MalToken = res.json()?;
+        self.save_token(cfg, token)
+    }
+
+    fn check_status(&self, res: Response) -> Result<Response> {
+        util::check_status(res, "MyAnimeList", Some(self.name()))
+    }
+
+    // MAL access tokens expire after a month, so retry once with a refreshed token
+    fn send(
+        &self,
+        cfg: &mut MendoConfig,
+        client: &Client,
+        build: impl Fn(&Client, &str) -> RequestBuilder,
+    ) -> Result<Response> {
+        let res = build(client, cfg.trackers.mal.token.as_str()).send()?;
+        if res.status() == StatusCode::UNAUTHORIZED && !cfg.trackers.mal.refresh_token.is_empty() {
+            self.refresh_token(cfg, client)?;
+            let res = build(client, cfg.trackers.mal.token.as_str()).send()?;
+            return self.check_status(res);
+        }
+        self.check_status(res)
+    }
+}
+
+// MyAnimeList has no repeating status, rereading is a flag on top of `reading`
+fn to_mal_status(status: &MediaListStatus) -> (&'static str, bool) {
+    match status {
+        MediaListStatus::Current => ("reading", false),
+        MediaListStatus::Repeating => ("reading", true),
+        MediaListStatus::Completed => ("completed", false),
+        MediaListStatus::Paused => ("on_hold", false),
+        MediaListStatus::Dropped => ("dropped", false),
+        MediaListStatus::Planning => ("plan_to_read", false),
+    }
+}
+
+fn from_mal_status(status: &str, is_rereading: bool) -> Option<MediaListStatus> {
+    match status {
+        "reading" if is_rereading => Some(MediaListStatus::Repeating),
+        "reading" => Some(MediaListStatus::Current),
+        "completed" => Some(MediaListStatus::Completed),
+        "on_hold" => Some(MediaListStatus::Paused),
+        "dropped" => Some(MediaListStatus::Dropped),
+        "plan_to_read" => Some(MediaListStatus::Planning),
+        _ => None,
+    }
+}
+
+// Everything but the novels counts as manga, like on Anilist
+fn is_format(media_type: &str, format: MediaFormat) -> bool {
+    let is_novel = matches!(media_type, "novel" | "light_novel");
+    is_novel == (format == MediaFormat::Novel)
+}
+
+impl Tracker for MyAnimeList {
+    fn name(&self) -> &'static str {
+        "mal"
+    }
+
+    fn authorize(&self, cfg: &mut MendoConfig, client: &Client) -> Result<()> {
+        if cfg.trackers.mal.client_id.is_empty() {
+            error!("trackers.mal.client_id is not set");
+            return Err(anyhow!(
+                "Set `trackers.mal.client_id` in the config file to the client ID of your MyAnimeList API app"
+            ));
+        }
+        // MAL only supports the `plain` PKCE method, so the challenge is the verifier itself
+        let code_verifier = CsrfToken::new_random_len(96).secret().to_string();
+        let state = CsrfToken::new_random().secret().to_string();
+        let url = Url::parse_with_params(
+            MAL_AUTH_URL,
+            &[
+                ("response_type", "code"),
+                ("client_id", cfg.trackers.mal.client_id.as_str()),
+                ("redirect_uri", cfg.url.as_ref()),
+                ("code_challenge", &code_verifier),
+                ("code_challenge_method", "plain"),
+                ("state", &state),
+            ],
+        )?;
+
+        output::say("Opening browser to authorize...");
+        open::that(url.to_string())?;
+        let (code, returned_state) = oauth::listen_for_code()?;
+        // Anything else did not come from the authorization started above
+        if returned_state.as_deref() != Some(state.as_str()) {
+            error!("MyAnimeList redirected with a wrong state");
+            return Err(MendoError::TrackerUnauthorized(self.name()).into());
+        }
+        debug!("Now will exchange MyAnimeList code for access token...");
+
+        let res = client
+            .post(&self.token_url)
+            .form(&[
+                ("client_id", cfg.trackers.mal.client_id.as_str()),
+                ("grant_type", "authorization_code"),
+                ("code", code.secret()),
+                ("redirect_uri", cfg.url.as_ref()),
+                ("code_verifier", &code_verifier),
+            ])
+            .send()?;
+        let token: MalToken = self.check_status(res)?.json()?;
+        info!("Successfully authenticated the user on MyAnimeList!");
+        self.save_token(cfg, token)
+    }
+
+    fn is_authorized(&self, cfg: &MendoConfig) -> bool {
+        !cfg.trackers.mal.token.is_empty()
+    }
+
+    // The client id is not a credential of the user, so it stays
+    fn forget(&self, cfg: &mut MendoConfig) -> bool {
+        let mal = &mut cfg.trackers.mal;
+        let had_token = !mal.token.is_empty() || !mal.refresh_token.is_empty();
+        mal.token.clear();
+        mal.refresh_token.clear();
+        had_token
+    }
+
+    // Novels often have the very title of their manga adaptation, so they are cached apart
+    fn search(
+        &self,
+        cfg: &mut MendoConfig,
+        title: &str,
+        format: MediaFormat,
+        client: &Client,
+    ) -> Result<i32> {
+        let local_media_data = self.data_dir.join(match format {
+            MediaFormat::Novel => "media_data_mal_novel.txt",
+            _ => "media_data_mal.txt",
+        });
+        if let Some(media_id) = util::find_local_media_id(&local_media_data, title)? {
+            return Ok(media_id);
+        }
+
+        info!(
+            "Searching MyAnimeList {:?} using name: `{}`...",
+            format, title
+        );
+        let res = self.send(cfg, client, |client, token| {
+            client
+                .get(format!("{}/manga", self.api_url))
+                .bearer_auth(token)
+                .query(&[
+                    ("q", title),
+                    ("limit", SEARCH_LIMIT),
+                    ("fields", "media_type"),
+                ])
+        })?;
+        let result: MalSearchResponse = res.json()?;
+        match result
+            .data
+            .into_iter()
+            .find(|item| is_format(&item.node.media_type, format))
+        {
+            Some(item) => {
+                debug!(
+                    "Found MyAnimeList id `{}` ({}, {}) for `{}`",
+                    item.node.id, item.node.title, item.node.media_type, title
+                );
+                util::append_local_data(&local_media_data, title, item.node.id)?;
+                Ok(item.node.id)
+            }
+            None => {
+                error!(
+                    "MyAnimeList did not return any {:?} result for `{}`",
+                    format, title
+                );
+                Err(anyhow!(
+                    "MyAnimeList did not return any result! Maybe recheck your archive filename?"
+                ))
+            }
+        }
+    }
+
+    fn get_entry(
+        &self,
+        cfg: &mut MendoConfig,
+        media_id: i32,
+        client: &Client,
+    ) -> Result<TrackerEntry> {
+        let res = self.send(cfg, client, |client, token| {
+            client
+                .get(format!("{}/manga/{}", self.api_url, media_id))
+                .bearer_auth(token)
+                .query(&[("fields", "my_list_status")])
+        })?;
+        let manga: MalManga = res.json()?;
+        // MAL creates the list entry on the first update, so a missing one is just 0
+        Ok(match manga.my_list_status {
+            Some(s) => TrackerEntry {
+                entry_id: None,
+                media_id,
+                progress: s.num_chapters_read,
+                progress_volumes: s.num_volumes_read,
+                status: s
+                    .status
+                    .as_deref()
+                    .and_then(|status| from_mal_status(status, s.is_rereading)),
+                created: false,
+            },
+            None => TrackerEntry {
+                entry_id: None,
+                media_id,
+                progress: 0,
+                progress_volumes: 0,
+                status: None,
+                created: true,
+            },
+        })
+    }
+
+    fn update_progress(
+        &self,
+        cfg: &mut MendoConfig,
+        entry: &TrackerEntry,
+        progress: &Progress,
+        client: &Client,
+    ) -> Result<()> {
+        info!(
+            "Updating progress of MyAnimeList manga `{}` with: progress `{}`, volumes `{:?}`...",
+            entry.media_id, progress.chapters, progress.volumes
+        );
+        // The status of an existing entry is left alone unless the series is done, like on
+        // the other trackers. A new one is created as being read.
+        let mut form = vec![("num_chapters_read", progress.chapters.to_string())];
+        if progress.completed {
+            form.push(("status", "completed".to_string()));
+        } else if entry.created {
+            form.push(("status", "reading".to_string()));
+        }
+        if let Some(volumes) = progress.volumes {
+            form.push(("num_volumes_read", volumes.to_string()));
+        }
+        self.send(cfg, client, |client, token| {
+            client
+                .patch(format!(
+                    "{}/manga/{}/my_list_status",
+                    self.api_url, entry.media_id
+                ))
+                .bearer_auth(token)
+                .form(&form)
+        })?;
+        Ok(())
+    }
+
+    fn restore(&self, cfg: &mut MendoConfig, entry: &TrackerEntry, client: &Client) -> Result<()> {
+        let url = format!("{}/manga/{}/my_list_status", self.api_url, entry.media_id);
+        if entry.created {
+            info!(
+                "Removing MyAnimeList manga `{}` from the list again...",
+                entry.media_id
+            );
+            self.send(cfg, client, |client, token| {
+                client.delete(&url).bearer_auth(token)
+            })?;
+            return Ok(());
+        }
+        info!(
+            "Restoring MyAnimeList manga `{}` to: status `{:?}`, progress `{}`, volumes `{}`...",
+            entry.media_id, entry.status, entry.progress, entry.progress_volumes
+        );
+        let mut form = vec![
+            ("num_chapters_read", entry.progress.to_string()),
+            ("num_volumes_read", entry.progress_volumes.to_string()),
+        ];
+        if let Some(status) = &entry.status {
+            let (status, is_rereading) = to_mal_status(status);
+            form.push(("status", status.to_string()));
+            form.push(("is_rereading", is_rereading.to_string()));
+        }
+        self.send(cfg, client, |client, token| {
+            client.patch(&url).bearer_auth(token).form(&form)
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, Matcher};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn mal(dir: &TempDir) -> MyAnimeList {
+        MyAnimeList {
+            data_dir: dir.path().to_path_buf(),
+            api_url: format!("{}/mal", mockito::server_url()),
+            token_url: format!("{}/mal/token", mockito::server_url()),
+        }
+    }
+
+    fn config(dir: &TempDir) -> MendoConfig<'static> {
+        let mut cfg = MendoConfig {
+            path: dir.path().join("default-config.yml"),
+            ..MendoConfig::default()
+        };
+        cfg.trackers.mal.client_id = "client".to_string();
+        cfg.trackers.mal.token = "token".to_string();
+        cfg.trackers.mal.refresh_token = "refresh".to_string();
+        cfg
+    }
+
+    fn entry(created: bool) -> TrackerEntry {
+        TrackerEntry {
+            entry_id: None,
+            media_id: 2,
+            progress: 10,
+            progress_volumes: 1,
+            status: if created {
+                None
+            } else {
+                Some(MediaListStatus::Current)
+            },
+            created,
+        }
+    }
+
+    fn progress(chapters: i32, volumes: Option<i32>, completed: bool) -> Progress {
+        Progress {
+            chapters,
+            volumes,
+            completed,
+        }
+    }
+
+    fn is_unauthorized(e: &anyhow::Error) -> bool {
+        matches!(
+            e.downcast_ref::<MendoError>(),
+            Some(MendoError::TrackerUnauthorized("mal"))
+        )
+    }
+
+    #[test]
+    fn searches_by_format_and_caches_the_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(&dir);
+        let search = mock("GET", "/mal/manga")
+            .match_header("authorization", "Bearer token")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("q".into(), "Overlord".into()),
+                Matcher::UrlEncoded("fields".into(), "media_type".into()),
+            ]))
+            .with_body(
+                r#"{"data": [
+                    {"node": {"id": 10, "title": "Overlord", "media_type": "light_novel"}},
+                    {"node": {"id": 20, "title": "Overlord", "media_type": "manga"}}
+                ]}"#,
+            )
+            .expect(2)
+            .create();
+
+        let mal = mal(&dir);
+        let client = Client::new();
+        assert_eq!(
+            mal.search(&mut cfg, "Overlord", MediaFormat::Manga, &client)
+                .unwrap(),
+            20
+        );
+        assert_eq!(
+            mal.search(&mut cfg, "Overlord", MediaFormat::Novel, &client)
+                .unwrap(),
+            10
+        );
+        // Both are cached apart now, so they are not searched again
+        assert_eq!(
+            mal.search(&mut cfg, "Overlord", MediaFormat::Manga, &client)
+                .unwrap(),
+            20
+        );
+        assert_eq!(
+            mal.search(&mut cfg, "Overlord", MediaFormat::Novel, &client)
+                .unwrap(),
+            10
+        );
+        search.assert();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("media_data_mal.txt")).unwrap(),
+            "Overlord - mediaId: 20\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("media_data_mal_novel.txt")).unwrap(),
+            "Overlord - mediaId: 10\n"
+        );
+    }
+
+    #[test]
+    fn fails_search_without_a_result_of_the_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(&dir);
+        let _search = mock("GET", "/mal/manga")
+            .match_query(Matcher::UrlEncoded("q".into(), "Berserk".into()))
+            .with_body(
+                r#"{"data": [{"node": {"id": 2, "title": "Berserk", "media_type": "manga"}}]}"#,
+            )
+            .create();
+        assert!(mal(&dir)
+            .search(&mut cfg, "Berserk", MediaFormat::Novel, &Client::new())
+            .is_err());
+    }
+
+    #[test]
+    fn gets_existing_and_missing_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(&dir);
+        let _existing = mock("GET", "/mal/manga/2")
+            .match_query(Matcher::UrlEncoded(
+                "fields".into(),
+                "my_list_status".into(),
+            ))
+            .with_body(
+                r#"{"id": 2, "my_list_status": {"status": "reading", "is_rereading": true,
+                    "num_chapters_read": 10, "num_volumes_read": 1}}"#,
+            )
+            .create();
+        let _missing = mock("GET", "/mal/manga/3")
+            .match_query(Matcher::UrlEncoded(
+                "fields".into(),
+                "my_list_status".into(),
+            ))
+            .with_body(r#"{"id": 3}"#)
+            .create();
+
+        let mal = mal(&dir);
+        let entry = mal.get_entry(&mut cfg, 2, &Client::new()).unwrap();
+        assert_eq!(entry.progress, 10);
+        assert_eq!(entry.progress_volumes, 1);
+        assert_eq!(entry.status, Some(MediaListStatus::Repeating));
+        assert!(!entry.created);
+
+        let entry = mal.get_entry(&mut cfg, 3, &Client::new()).unwrap();
+        assert_eq!(entry.progress, 0);
+        assert_eq!(entry.status, None);
+        assert!(entry.created);
+    }
+
+    #[test]
+    fn sends_a_status_only_when_completing_or_creating() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(&dir);
+        let mal = mal(&dir);
+        let client = Client::new();
+        for (entry, progress, form) in &[
+            (
+                entry(false),
+                progress(11, None, false),
+                "num_chapters_read=11",
+            ),
+            (
+                entry(true),
+                progress(1, None, false),
+                "num_chapters_read=1&status=reading",
+            ),
+            (
+                entry(false),
+                progress(12, Some(2), true),
+                "num_chapters_read=12&status=completed&num_volumes_read=2",
+            ),
+        ] {
+            let update = mock("PATCH", "/mal/manga/2/my_list_status")
+                .match_header("content-type", "application/x-www-form-urlencoded")
+                .match_body(*form)
+                .create();
+            mal.update_progress(&mut cfg, entry, progress, &client)
+                .unwrap();
+            update.assert();
+        }
+    }
+
+    #[test]
+    fn refreshes_the_token_once_on_401() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(&dir);
+        let _expired = mock("GET", "/mal/manga/2")
+            .match_header("authorization", "Bearer token")
+            .match_query(Matcher::Any)
+            .with_status(401)
+            .create();
+        let refresh = mock("POST", "/mal/token")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("grant_type".into(), "refresh_token".into()),
+                Matcher::UrlEncoded("refresh_token".into(), "refresh".into()),
+            ]))
+            .with_body(r#"{"access_token": "new", "refresh_token": "new-refresh"}"#)
+            .create();
+        let _refreshed = mock("GET", "/mal/manga/2")
+            .match_header("authorization", "Bearer new")
+            .match_query(Matcher::Any)
+            .with_body(r#"{"id": 2}"#)
+            .create();
+
+        let entry = mal(&dir).get_entry(&mut cfg, 2, &Client::new()).unwrap();
+        refresh.assert();
+        assert!(entry.created);
+        assert_eq!(cfg.trackers.mal.token, "new");
+        assert_eq!(cfg.trackers.mal.refresh_token, "new-refresh");
+        // The new token is saved for the next run
+        assert!(fs::read_to_string(&cfg.path)
+            .unwrap()
+            .contains("new-refresh"));
+    }
+
+    #[test]
+    fn is_unauthorized_when_the_refresh_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(&dir);
+        let _expired = mock("GET", "/mal/manga/4")
+            .match_query(Matcher::Any)
+            .with_status(401)
+            .create();
+        let _refresh = mock("POST", "/mal/token")
+            .with_status(400)
+            .with_body(r#"{"error": "invalid_grant"}"#)
+            .create();
+        let e = mal(&dir)
+            .get_entry(&mut cfg, 4, &Client::new())
+            .unwrap_err();
+        assert!(is_unauthorized(&e), "{:#}", e);
+        assert_eq!(cfg.trackers.mal.token, "token");
+    }
+
+    #[test]
+    fn is_unauthorized_without_a_refresh_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(&dir);
+        cfg.trackers.mal.refresh_token.clear();
+        let _expired = mock("GET", "/mal/manga/5")
+            .match_query(Matcher::Any)
+            .with_status(401)
+            .create();
+        let e = mal(&dir)
+            .get_entry(&mut cfg, 5, &Client::new())
+            .unwrap_err();
+        assert!(is_unauthorized(&e), "{:#}", e);
+    }
+
+    #[test]
+    fn restores_the_status_or_removes_created_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = config(&dir);
+        let mal = mal(&dir);
+        let client = Client::new();
+        let restore = mock("PATCH", "/mal/manga/2/my_list_status")
+            .match_body("num_chapters_read=10&num_volumes_read=1&status=reading&is_rereading=false")
+            .create();
+        mal.restore(&mut cfg, &entry(false), &client).unwrap();
+        restore.assert();
+
+        let delete = mock("DELETE", "/mal/manga/2/my_list_status").create();
+        mal.restore(&mut cfg, &entry(true), &client).unwrap();
+        delete.assert();
+    }
+}
@@ -0,0 +1,423 @@
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use reqwest::blocking::Client;
+use std::path::Path;
+
+use crate::anilist::model::{MediaFormat, MediaListStatus};
+use crate::error::MendoError;
+use crate::progress::{Progress, Read};
+use crate::util::MendoConfig;
+
+pub mod anilist;
+pub mod kitsu;
+pub mod mal;
+
+// A list entry as seen by one tracker, ids are only meaningful to that tracker
+#[derive(Debug, Clone)]
+pub struct TrackerEntry {
+    pub entry_id: Option<i32>,
+    pub media_id: i32,
+    pub progress: i32,
+    pub progress_volumes: i32,
+    // In the terms of Anilist, `None` while the entry does not exist
+    pub status: Option<MediaListStatus>,
+    // Set when `get_entry` had to create the entry, undoing the update removes it again
+    pub created: bool,
+}
+
+pub trait Tracker {
+    fn name(&self) -> &'static str;
+
+    // Runs the interactive authorization and stores the credentials in the config file
+    fn authorize(&self, cfg: &mut MendoConfig, client: &Client) -> Result<()>;
+
+    fn is_authorized(&self, cfg: &MendoConfig) -> bool;
+
+    // Drops the credentials of this tracker from `cfg`, returns whether there were any
+    fn forget(&self, cfg: &mut MendoConfig) -> bool;
+
+    // Resolves a title to the media id of this tracker, among media of the same format only
+    fn search(
+        &self,
+        cfg: &mut MendoConfig,
+        title: &str,
+        format: MediaFormat,
+        client: &Client,
+    ) -> Result<i32>;
+
+    // Returns the list entry of the user, creating one if the title is not on the list yet
+    fn get_entry(
+        &self,
+        cfg: &mut MendoConfig,
+        media_id: i32,
+        client: &Client,
+    ) -> Result<TrackerEntry>;
+
+    // Trackers without volume progress only update the chapters
+    fn update_progress(
+        &self,
+        cfg: &mut MendoConfig,
+        entry: &TrackerEntry,
+        progress: &Progress,
+        client: &Client,
+    ) -> Result<()>;
+
+    // Puts an entry as returned by `get_entry` back, status included
+    fn restore(&self, cfg: &mut MendoConfig, entry: &TrackerEntry, client: &Client) -> Result<()>;
+}
+
+// Every tracker once, for commands that are not limited to the enabled ones
+pub fn all(data_dir: &Path) -> Vec<Box<dyn Tracker>> {
+    vec![
+        Box::new(anilist::Anilist::new(data_dir)),
+        Box::new(mal::MyAnimeList::new(data_dir)),
+        Box::new(kitsu::Kitsu::new(data_dir)),
+    ]
+}
+
+// Names accepted in `trackers.enabled` and by `from_name`
+pub const NAMES: [&str; 4] = ["anilist", "mal", "myanimelist", "kitsu"];
+
+pub fn from_name(name: &str, data_dir: &Path) -> Result<Box<dyn Tracker>> {
+    match name {
+        "anilist" => Ok(Box::new(anilist::Anilist::new(data_dir))),
+        "mal" | "myanimelist" => Ok(Box::new(mal::MyAnimeList::new(data_dir))),
+        "kitsu" => Ok(Box::new(kitsu::Kitsu::new(data_dir))),
+        _ => {
+            error!("Unknown tracker `{}`", name);
+            Err(anyhow!(
+                "Unknown tracker `{}`. Supported trackers are anilist, mal and kitsu",
+                name
+            ))
+        }
+    }
+}
+
+pub fn from_config(cfg: &MendoConfig, data_dir: &Path) -> Result<Vec<Box<dyn Tracker>>> {
+    if cfg.trackers.enabled.is_empty() {
+        error!("No tracker is configured");
+        return Err(anyhow!(
+            "No tracker is configured. Add one to `trackers.enabled`"
+        ));
+    }
+    cfg.trackers
+        .enabled
+        .iter()
+        .map(|name| from_name(name, data_dir))
+        .collect()
+}
+
+// What `update_all` updates the trackers with
+pub struct Update<'a> {
+    pub title: &'a str,
+    pub format: MediaFormat,
+    pub read: &'a Read,
+    // Skip the search on Anilist and MyAnimeList
+    pub anilist_id: Option<i32>,
+    pub mal_id: Option<i32>,
+    // The chapter count of a series that should be marked completed once it is reached
+    pub complete_at: Option<i32>,
+}
+
+// Reads the entry of one tracker and brings it to the new progress, computing that from
+// the entry if this is the first tracker. The entry is kept in `previous` before it is
+// updated, so a failed update is put back as well.
+fn update_one(
+    cfg: &mut MendoConfig,
+    tracker: &dyn Tracker,
+    update: &Update,
+    new_progress: &mut Option<Progress>,
+    previous: &mut Vec<TrackerEntry>,
+    client: &Client,
+) -> Result<()> {
+    if !tracker.is_authorized(cfg) {
+        error!("{} is not authorized", tracker.name());
+        return Err(match tracker.name() {
+            "anilist" => MendoError::Unauthorized,
+            name => MendoError::TrackerUnauthorized(name),
+        }
+        .into());
+    }
+    let media_id = match (tracker.name(), update.anilist_id, update.mal_id) {
+        ("anilist", Some(media_id), _) | ("mal", _, Some(media_id)) => media_id,
+        _ => tracker.search(cfg, update.title, update.format, client)?,
+    };
+    let entry = tracker.get_entry(cfg, media_id, client)?;
+    let progress = new_progress.get_or_insert_with(|| {
+        let mut progress = update.read.apply(entry.progress, entry.progress_volumes);
+        progress.completed =
+            matches!(update.complete_at, Some(total) if progress.chapters >= total);
+        progress
+    });
+    previous.push(entry);
+    tracker.update_progress(
+        cfg,
+        previous.last().expect("Safe because it was just pushed"),
+        progress,
+        client,
+    )
+}
+
+// Computes the new progress from the entry of the first tracker and updates every
+// tracker to it, so all of them agree even if they were out of sync before.
+// The entries as they were before the update are returned to be able to revert it.
+// If one tracker fails, the ones updated before it are put back before returning the error.
+pub fn update_all(
+    cfg: &mut MendoConfig,
+    trackers: &[Box<dyn Tracker>],
+    update: &Update,
+    client: &Client,
+) -> Result<(Progress, Vec<TrackerEntry>)> {
+    let mut new_progress = None;
+    let mut previous = Vec::new();
+
+    for tracker in trackers {
+        if let Err(e) = update_one(
+            cfg,
+            tracker.as_ref(),
+            update,
+            &mut new_progress,
+            &mut previous,
+            client,
+        ) {
+            for (tracker, entry) in trackers.iter().zip(&previous) {
+                info!(
+                    "Putting {} entry of media `{}` back after the failed update",
+                    tracker.name(),
+                    entry.media_id
+                );
+                if let Err(e) = tracker.restore(cfg, entry, client) {
+                    error!(
+                        "Could not put {} entry of media `{}` back: {:#}",
+                        tracker.name(),
+                        entry.media_id,
+                        e
+                    );
+                }
+            }
+            return Err(e);
+        }
+    }
+
+    Ok((
+        new_progress.expect("Safe because there is at least one tracker"),
+        previous,
+    ))
+}
+
+// Puts every entry back as it was before `update_all`, unless another update moved it on
+// since. Entries that `update_all` created are removed again.
+pub fn revert_all(
+    cfg: &mut MendoConfig,
+    trackers: &[Box<dyn Tracker>],
+    previous: &[TrackerEntry],
+    progress: &Progress,
+    client: &Client,
+) -> Result<()> {
+    for (tracker, entry) in trackers.iter().zip(previous) {
+        let current = tracker.get_entry(cfg, entry.media_id, client)?;
+        if current.progress != progress.chapters {
+            warn!(
+                "{} entry of media `{}` is at {} now instead of {}, it is not reverted",
+                tracker.name(),
+                entry.media_id,
+                current.progress,
+                progress.chapters
+            );
+            continue;
+        }
+        info!(
+            "Reverting {} entry of media `{}` to {:?}, progress {}",
+            tracker.name(),
+            entry.media_id,
+            entry.status,
+            entry.progress
+        );
+        tracker.restore(cfg, entry, client)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Keeps its entry in memory and writes down every call that changes it
+    struct Fake {
+        name: &'static str,
+        entry: RefCell<TrackerEntry>,
+        fail_update: bool,
+        calls: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Fake {
+        fn boxed(
+            name: &'static str,
+            progress: i32,
+            fail_update: bool,
+            calls: &Rc<RefCell<Vec<String>>>,
+        ) -> Box<dyn Tracker> {
+            Box::new(Fake {
+                name,
+                entry: RefCell::new(TrackerEntry {
+                    entry_id: Some(1),
+                    media_id: 1,
+                    progress,
+                    progress_volumes: 0,
+                    status: Some(MediaListStatus::Current),
+                    created: false,
+                }),
+                fail_update,
+                calls: Rc::clone(calls),
+            })
+        }
+    }
+
+    impl Tracker for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn authorize(&self, _cfg: &mut MendoConfig, _client: &Client) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn is_authorized(&self, _cfg: &MendoConfig) -> bool {
+            true
+        }
+
+        fn forget(&self, _cfg: &mut MendoConfig) -> bool {
+            unimplemented!()
+        }
+
+        fn search(
+            &self,
+            _cfg: &mut MendoConfig,
+            _title: &str,
+            _format: MediaFormat,
+            _client: &Client,
+        ) -> Result<i32> {
+            Ok(1)
+        }
+
+        fn get_entry(
+            &self,
+            _cfg: &mut MendoConfig,
+            _media_id: i32,
+            _client: &Client,
+        ) -> Result<TrackerEntry> {
+            Ok(self.entry.borrow().clone())
+        }
+
+        fn update_progress(
+            &self,
+            _cfg: &mut MendoConfig,
+            _entry: &TrackerEntry,
+            progress: &Progress,
+            _client: &Client,
+        ) -> Result<()> {
+            if self.fail_update {
+                return Err(anyhow!("{} is down", self.name));
+            }
+            self.entry.borrow_mut().progress = progress.chapters;
+            self.calls
+                .borrow_mut()
+                .push(format!("{} update {}", self.name, progress.chapters));
+            Ok(())
+        }
+
+        fn restore(
+            &self,
+            _cfg: &mut MendoConfig,
+            entry: &TrackerEntry,
+            _client: &Client,
+        ) -> Result<()> {
+            *self.entry.borrow_mut() = entry.clone();
+            self.calls
+                .borrow_mut()
+                .push(format!("{} restore {}", self.name, entry.progress));
+            Ok(())
+        }
+    }
+
+    fn update(read: &Read) -> Update<'_> {
+        Update {
+            title: "Berserk",
+            format: MediaFormat::Manga,
+            read,
+            anilist_id: None,
+            mal_id: None,
+            complete_at: Some(12),
+        }
+    }
+
+    #[test]
+    fn syncs_every_tracker_to_the_first() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let trackers = vec![
+            Fake::boxed("one", 11, false, &calls),
+            Fake::boxed("two", 3, false, &calls),
+        ];
+        let (progress, previous) = update_all(
+            &mut MendoConfig::default(),
+            &trackers,
+            &update(&Read::NextChapter),
+            &Client::new(),
+        )
+        .unwrap();
+        assert_eq!(progress.chapters, 12);
+        assert!(progress.completed);
+        assert_eq!(previous.len(), 2);
+        assert_eq!(previous[1].progress, 3);
+        assert_eq!(*calls.borrow(), vec!["one update 12", "two update 12"]);
+    }
+
+    #[test]
+    fn puts_trackers_back_when_one_fails() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let trackers = vec![
+            Fake::boxed("one", 5, false, &calls),
+            Fake::boxed("two", 5, true, &calls),
+        ];
+        let e = update_all(
+            &mut MendoConfig::default(),
+            &trackers,
+            &update(&Read::Chapter(7)),
+            &Client::new(),
+        )
+        .unwrap_err();
+        assert_eq!(e.to_string(), "two is down");
+        assert_eq!(
+            *calls.borrow(),
+            vec!["one update 7", "one restore 5", "two restore 5"]
+        );
+    }
+
+    #[test]
+    fn reverts_only_entries_that_did_not_move_on() {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let trackers = vec![
+            Fake::boxed("one", 5, false, &calls),
+            Fake::boxed("two", 5, false, &calls),
+        ];
+        let mut cfg = MendoConfig::default();
+        let client = Client::new();
+        let (progress, previous) =
+            update_all(&mut cfg, &trackers, &update(&Read::Chapter(7)), &client).unwrap();
+        // Another update read chapter 8 on the second tracker meanwhile
+        trackers[1]
+            .update_progress(
+                &mut cfg,
+                &previous[1],
+                &Read::Chapter(8).apply(7, 0),
+                &client,
+            )
+            .unwrap();
+        calls.borrow_mut().clear();
+
+        revert_all(&mut cfg, &trackers, &previous, &progress, &client).unwrap();
+        assert_eq!(*calls.borrow(), vec!["one restore 5"]);
+    }
+}
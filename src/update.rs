@@ -0,0 +1,410 @@
+use anyhow::Result;
+use log::{debug, error, info, warn};
+use reqwest::blocking::Client;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::anilist::model::{MediaFormat, MediaListStatus, MediaType};
+use crate::anilist::request;
+use crate::comic_info;
+use crate::lock::{self, QueuedUpdate};
+use crate::output::{self, Record};
+use crate::progress::{self, Progress, Read};
+use crate::series::{self, SeriesConfig, SeriesTable};
+use crate::tracker::{self, Tracker, TrackerEntry};
+use crate::util::{self, MendoConfig};
+
+// A queued archive, resolved to its title and what reading it means
+struct Resolved {
+    name: String,
+    anilist_id: Option<i32>,
+    // Only known from the `Web` field of ComicInfo.xml
+    mal_id: Option<i32>,
+    format: MediaFormat,
+    series: SeriesConfig,
+    read: Read,
+}
+
+fn resolve(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    series_table: &SeriesTable,
+    update: &QueuedUpdate,
+    client: &Client,
+) -> Result<Option<Resolved>> {
+    // Archives given by their path are opened for ComicInfo.xml, only the file name is parsed
+    let path = Path::new(&update.filename);
+    let filename = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&update.filename);
+    let comic_info = if path.is_file() {
+        comic_info::read(path)
+    } else {
+        None
+    };
+    let pinned = series_table.match_filename(filename);
+    let name = match comic_info.as_ref().and_then(|info| info.series.clone()) {
+        Some(series) => {
+            debug!("Got manga name: `{}` from ComicInfo.xml", series);
+            series
+        }
+        None => {
+            let filename_pattern = match pinned {
+                Some((_, pattern)) => pattern,
+                None => update.regexp.as_str(),
+            };
+            util::get_manga_name(filename, filename_pattern)?.to_string()
+        }
+    };
+    let format = util::format_from_filename(filename, &cfg.parser.novel_extensions);
+    debug!("Got {:?} name: `{}`", format, &name);
+
+    // An id in ComicInfo.xml skips the title search, and so does a pinned series
+    let mal_id = comic_info.as_ref().and_then(|info| info.mal_id);
+    let anilist_id = match (comic_info.as_ref().and_then(|info| info.anilist_id), pinned) {
+        (Some(media_id), _) | (None, Some((media_id, _))) => Some(media_id),
+        // Series settings and covers are keyed by Anilist id, so only look it up when needed
+        _ if series_table.is_empty()
+            && !cfg.trackers.enabled.iter().any(|name| name == "anilist") =>
+        {
+            None
+        }
+        _ => {
+            let by_mal_id = mal_id.and_then(|id_mal| {
+                match request::search_media_by_mal_id(cfg, id_mal, MediaType::Manga, client) {
+                    Ok(query_result) => query_result
+                        .data
+                        .map(|media_resp| media_resp.media.media_id),
+                    Err(e) => {
+                        debug!("Could not find MyAnimeList id `{}`: {}", id_mal, e);
+                        None
+                    }
+                }
+            });
+            match by_mal_id {
+                Some(media_id) => Some(media_id),
+                None => Some(util::get_media_id_by_name(
+                    cfg,
+                    data_dir,
+                    &name,
+                    Some(format),
+                    client,
+                )?),
+            }
+        }
+    };
+    let series =
+        anilist_id.map_or_else(SeriesConfig::default, |media_id| series_table.get(media_id));
+    if series.ignore {
+        output::emit(
+            &format!(
+                "`{}` is ignored in series.yml, progress is not updated.",
+                name
+            ),
+            Record {
+                event: "skipped",
+                media_id: anilist_id,
+                title: Some(name.to_string()),
+                message: Some("ignored in series.yml".to_string()),
+                ..Record::default()
+            },
+        );
+        return Ok(None);
+    }
+
+    let from_comic_info = match &comic_info {
+        Some(info) => progress::read_from_comic_info(
+            info,
+            &name,
+            format,
+            &cfg.parser.chapter_policy,
+            data_dir,
+        )?,
+        None => None,
+    };
+    let read = match from_comic_info {
+        Some(read) => read,
+        None => progress::read_from_filename(
+            filename,
+            &name,
+            format,
+            &cfg.parser.chapter_policy,
+            data_dir,
+        )?,
+    }
+    .with_offset(series.chapter_offset);
+    if let Read::Skip(reason) = &read {
+        output::emit(
+            &format!("Progress is not updated because {}.", reason),
+            Record {
+                event: "skipped",
+                media_id: anilist_id,
+                title: Some(name.to_string()),
+                message: Some(reason.clone()),
+                ..Record::default()
+            },
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(Resolved {
+        name,
+        anilist_id,
+        mal_id,
+        format,
+        series,
+        read,
+    }))
+}
+
+// An applied update, kept to notify about it once the lock is released
+#[cfg_attr(not(target_family = "unix"), allow(dead_code))]
+struct Applied {
+    title: String,
+    media_id: Option<i32>,
+    format: MediaFormat,
+    progress: Progress,
+    // The entries before the update, all that is needed to undo it
+    previous: Vec<TrackerEntry>,
+    total: Option<i32>,
+    cover: Option<PathBuf>,
+}
+
+fn apply(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    trackers: &[Box<dyn Tracker>],
+    resolved: &Resolved,
+    client: &Client,
+) -> Result<Applied> {
+    let Resolved {
+        name,
+        anilist_id,
+        mal_id,
+        format,
+        series,
+        read,
+    } = resolved;
+    let media = match anilist_id {
+        Some(media_id) => request::query_media(cfg, *media_id, client)?
+            .data
+            .map(|media_resp| media_resp.media),
+        None => None,
+    };
+    let complete_at = media
+        .as_ref()
+        .filter(|_| series.auto_complete)
+        .and_then(|media| media.chapters);
+    let update = tracker::Update {
+        title: name,
+        format: *format,
+        read,
+        anilist_id: *anilist_id,
+        mal_id: *mal_id,
+        complete_at,
+    };
+    let (progress, previous) = tracker::update_all(cfg, trackers, &update, client)?;
+
+    let title = match (&media, series.title_language) {
+        (Some(media), Some(language)) => series::preferred_title(media, language),
+        _ => name,
+    };
+    let first = &previous[0];
+    let text = match (format, progress.volumes) {
+        (MediaFormat::Novel, Some(volumes)) => format!("`{}` is now at volume {}.", title, volumes),
+        _ => format!("`{}` is now at chapter {}.", title, progress.chapters),
+    };
+    output::emit(
+        &text,
+        Record {
+            event: "updated",
+            media_id: *anilist_id,
+            title: Some(title.to_string()),
+            old_progress: Some(first.progress),
+            new_progress: Some(progress.chapters),
+            old_volumes: progress.volumes.map(|_| first.progress_volumes),
+            new_volumes: progress.volumes,
+            new_status: if progress.completed {
+                Some(MediaListStatus::Completed)
+            } else {
+                None
+            },
+            ..Record::default()
+        },
+    );
+
+    // A missing cover should not hide the notification
+    let cover = match &media {
+        Some(media) if cfg.notifications.enabled && cfg.cache.covers => {
+            util::get_cover(media, data_dir, client).unwrap_or_else(|e| {
+                warn!("Could not get the cover of `{}`: {}", title, e);
+                None
+            })
+        }
+        _ => None,
+    };
+    let total = media.as_ref().and_then(|media| match format {
+        MediaFormat::Novel => media.volumes,
+        _ => media.chapters,
+    });
+    Ok(Applied {
+        title: title.to_string(),
+        media_id: *anilist_id,
+        format: *format,
+        progress,
+        previous,
+        total,
+        cover,
+    })
+}
+
+// Waits for the notification to close, which takes long enough that other instances must
+// not be kept waiting meanwhile. The lock is only taken again to undo the update.
+#[cfg(target_family = "unix")]
+fn notify(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    trackers: &[Box<dyn Tracker>],
+    applied: &Applied,
+    client: &Client,
+) -> Result<()> {
+    let undo = util::notify_updated(
+        &applied.title,
+        &applied.progress,
+        applied.format,
+        applied.total,
+        applied.cover.as_deref(),
+        cfg.notifications.timeout,
+    )?;
+    if !undo {
+        return Ok(());
+    }
+    let _lock = lock::acquire(data_dir, Duration::from_secs(cfg.lock_timeout))?;
+    tracker::revert_all(cfg, trackers, &applied.previous, &applied.progress, client)?;
+    let first = &applied.previous[0];
+    output::emit(
+        &format!("Progress of `{}` is reverted.", applied.title),
+        Record {
+            event: "reverted",
+            media_id: applied.media_id,
+            title: Some(applied.title.clone()),
+            old_progress: Some(applied.progress.chapters),
+            new_progress: Some(first.progress),
+            new_status: first.status.clone(),
+            ..Record::default()
+        },
+    );
+    Ok(())
+}
+
+fn report_combined(filename: &str) {
+    info!("`{}` was applied by another mendo instance", filename);
+    output::emit(
+        &format!("`{}` was applied by another mendo instance.", filename),
+        Record {
+            event: "combined",
+            message: Some(filename.to_string()),
+            ..Record::default()
+        },
+    );
+}
+
+// Queues the archive, then applies it together with every other queued update once
+// this instance holds the lock. If another instance got there first, it is already done.
+pub fn update(
+    cfg: &mut MendoConfig,
+    data_dir: &Path,
+    filename: &str,
+    regexp: &str,
+    client: &Client,
+) -> Result<()> {
+    // Whoever applies the queue may run in another directory
+    let filename = match fs::canonicalize(filename) {
+        Ok(path) => path.display().to_string(),
+        Err(_) => filename.to_string(),
+    };
+    let filename = filename.as_str();
+    let own = QueuedUpdate::new(filename, regexp);
+    lock::push(data_dir, &own)?;
+    let lock = match lock::acquire(data_dir, Duration::from_secs(cfg.lock_timeout)) {
+        Ok(lock) => lock,
+        // A failed update must not be applied later by whichever instance comes next
+        Err(e) => {
+            if lock::remove(data_dir, &own.id)? {
+                return Err(e);
+            }
+            report_combined(filename);
+            return Ok(());
+        }
+    };
+    let queued = lock::drain(data_dir)?;
+    if !queued.iter().any(|update| update.id == own.id) {
+        report_combined(filename);
+        return Ok(());
+    }
+    info!("Applying {} queued updates", queued.len());
+
+    let series_table = SeriesTable::load(data_dir)?;
+    let mut first_error = None;
+    let mut resolved: Vec<Resolved> = Vec::new();
+    for update in &queued {
+        match resolve(cfg, data_dir, &series_table, update, client) {
+            Ok(Some(next)) => {
+                // Reads of the same title are merged into a single read-modify-write
+                let combined = resolved
+                    .iter_mut()
+                    .rev()
+                    .find(|r| {
+                        r.name == next.name
+                            && r.anilist_id == next.anilist_id
+                            && r.format == next.format
+                    })
+                    .and_then(|r| r.read.combine(&next.read).map(|read| (r, read)));
+                match combined {
+                    Some((r, read)) => {
+                        debug!("Combined `{}` into {:?}", update.filename, read);
+                        r.read = read;
+                    }
+                    None => resolved.push(next),
+                }
+            }
+            Ok(None) => (),
+            Err(e) => {
+                error!("Could not resolve `{}`: {:#}", update.filename, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    let trackers = tracker::from_config(cfg, data_dir)?;
+    let mut applied = Vec::new();
+    for r in &resolved {
+        match apply(cfg, data_dir, &trackers, r, client) {
+            Ok(a) => applied.push(a),
+            Err(e) => {
+                error!("Could not update `{}`: {:#}", r.name, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    drop(lock);
+
+    #[cfg(target_family = "unix")]
+    if cfg.notifications.enabled {
+        for a in &applied {
+            if let Err(e) = notify(cfg, data_dir, &trackers, a, client) {
+                error!("Could not undo `{}`: {:#}", a.title, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    #[cfg(not(target_family = "unix"))]
+    let _ = applied;
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
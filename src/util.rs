@@ -1,19 +1,30 @@
 use anyhow::{anyhow, Result};
 use directories::ProjectDirs;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use quick_xml::events::Event;
+use quick_xml::Reader;
 use regex::Regex;
-use reqwest::blocking::Client;
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::{self, File, OpenOptions};
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::anilist::model::{MediaType, User};
+use crate::anilist::model::{Media, MediaFormat, MediaList, MediaListStatus, MediaType, User};
 use crate::anilist::request;
+use crate::config::{
+    self, CacheConfig, NotificationsConfig, ParserConfig, TrackersConfig, CONFIG_VERSION,
+};
+use crate::error::MendoError;
+use crate::logging::LoggingConfig;
+use crate::progress::Progress;
 
 #[cfg(target_family = "unix")]
-use notify_rust::{Notification, NotificationHandle};
+use notify_rust::{Hint, Notification};
 
 #[derive(Serialize, Deserialize, Debug)]
 struct AnilistToken<'a> {
@@ -26,43 +37,103 @@ struct AnilistToken<'a> {
 // have to use String here because of how Confy serdes the structs
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MendoConfig<'a> {
+    // Schema version of the file, see `config::CONFIG_VERSION`
+    pub version: u64,
     pub id: i32,
     pub secret: Cow<'a, str>,
     pub name: Cow<'a, str>,
     pub url: Cow<'a, str>,
     pub token: Cow<'a, str>,
+    // Profile used when --profile is not given, only read from the main config file
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub parser: ParserConfig,
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    #[serde(default)]
+    pub trackers: TrackersConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    // Seconds to wait for another running mendo before giving up
+    #[serde(default = "default_lock_timeout")]
+    pub lock_timeout: u64,
+    // Where the file was loaded from and where the data of mendo lives, set by `config::load`
+    #[serde(skip)]
+    pub path: PathBuf,
+    #[serde(skip)]
+    pub data_dir: PathBuf,
+}
+
+fn default_lock_timeout() -> u64 {
+    60
 }
 
 impl Default for MendoConfig<'_> {
     fn default() -> Self {
         MendoConfig {
+            version: CONFIG_VERSION,
             id: 3891,
             secret: Cow::Borrowed("ASEXk9zRXXkpbXSrzxNn89fuGDyiVmS3qkszaUXb"),
             name: Cow::Borrowed("mendo"),
             url: Cow::Borrowed("http://localhost:8080/callback"),
             token: Cow::Borrowed("Leave this field."),
+            default_profile: None,
+            parser: ParserConfig::default(),
+            notifications: NotificationsConfig::default(),
+            trackers: TrackersConfig::default(),
+            cache: CacheConfig::default(),
+            logging: LoggingConfig::default(),
+            lock_timeout: default_lock_timeout(),
+            path: PathBuf::new(),
+            data_dir: PathBuf::new(),
         }
     }
 }
 
 impl MendoConfig<'_> {
-    pub fn access_token_is_valid(&mut self) -> bool {
+    pub fn access_token_is_valid(&self) -> bool {
         self.token != "Leave this field."
     }
-}
 
-pub fn get_conf_dir(qualifier: &str, organization: &str, application: &str) -> Result<PathBuf> {
-    let proj_dirs = ProjectDirs::from(&qualifier, &organization, &application)
-        .expect("Could not retrieve ProjectDirs, maybe you are using an unsupported OS");
-    Ok(proj_dirs.config_dir().to_path_buf())
+    // Every credential in the config file, for the log redaction
+    pub fn secrets(&self) -> Vec<String> {
+        let mut secrets = vec![
+            self.secret.to_string(),
+            self.trackers.mal.token.clone(),
+            self.trackers.mal.refresh_token.clone(),
+            self.trackers.kitsu.token.clone(),
+        ];
+        if self.access_token_is_valid() {
+            secrets.push(self.token.to_string());
+        }
+        secrets.retain(|secret| !secret.is_empty());
+        secrets
+    }
+
+    // Only forgets the Anilist token, credentials of other trackers are kept
+    pub fn reset_token(&mut self) {
+        self.token = Cow::Borrowed("Leave this field.");
+    }
 }
 
 pub fn get_data_dir(qualifier: &str, organization: &str, application: &str) -> Result<PathBuf> {
-    let proj_dirs = ProjectDirs::from(&qualifier, &organization, &application)
+    let proj_dirs = ProjectDirs::from(qualifier, organization, application)
         .expect("Could not retrieve ProjectDirs, maybe you are using an unsupported OS");
     Ok(proj_dirs.data_dir().to_path_buf())
 }
 
+// Cleanup that is fine with the file being gone already, returns whether it was there
+pub fn remove_if_exists(path: &Path) -> Result<bool> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
 pub fn create_data_dir(data_dir: &Path) -> Result<()> {
     if !data_dir.exists() {
         debug!("Project data dir does not exist, creating them...");
@@ -72,147 +143,306 @@ pub fn create_data_dir(data_dir: &Path) -> Result<()> {
     Ok(())
 }
 
-pub fn create_proj_conf(qualifier: &str, organization: &str, application: &str) -> Result<()> {
-    let proj_dirs = ProjectDirs::from(&qualifier, &organization, &application)
-        .expect("Could not retrieve ProjectDirs, maybe you are using an unsupported OS");
-    let conf_dir = proj_dirs.config_dir();
-
+pub fn cfg_save_token(cfg: &mut MendoConfig, res_token: &str) -> Result<()> {
+    let anilist_token: AnilistToken = serde_json::from_str(res_token)?;
     debug!(
-        "{} configuration file does not exist. I will now create a configuration file at {}",
-        &application,
-        conf_dir.display()
+        "Deserialized anilist token of type `{}`, expires in {}s",
+        anilist_token.token_type, anilist_token.expires_in
     );
-    confy::store(&application, None, MendoConfig::default())?;
-    debug!("Default config file saved.");
 
+    cfg.url = Cow::Borrowed("http://localhost:8080/callback");
+    cfg.token = Cow::Owned(anilist_token.access_token.to_string());
+    config::store(&*cfg)?;
+    // The new token may belong to another account than the cached profile
+    if remove_if_exists(&cfg.data_dir.join("user.yml"))? {
+        debug!("Removed user.yml of the previous token");
+    }
+
+    info!("Configuration with access token is saved!");
     Ok(())
 }
 
-pub fn cfg_save_token<'a>(
-    application: &str,
-    cfg: &'a mut MendoConfig,
-    res_token: &'a str,
-) -> Result<MendoConfig<'a>> {
-    let anilist_token: AnilistToken = serde_json::from_str(&res_token)?;
-    debug!("Deserialized anilist token:\n{:#?}\n", anilist_token);
-
-    let client_secret = &cfg.secret;
-    let client_name = &cfg.name;
+fn refresh_user_profile(cfg: &mut MendoConfig, path: &Path, client: &Client) -> Result<()> {
+    let query_result = request::query_user(cfg, client)?;
+    if let Some(viewer_resp) = query_result.data {
+        viewer_resp.viewer.dump_user_info(path)?;
+    }
+    Ok(())
+}
 
-    let cfg_with_token = MendoConfig {
-        id: cfg.id,
-        secret: Cow::Borrowed(client_secret),
-        name: Cow::Borrowed(client_name),
-        url: Cow::Borrowed("http://localhost:8080/callback"),
-        token: Cow::Borrowed(anilist_token.access_token),
-    };
-    confy::store(&application, None, &cfg_with_token)?;
+// Anilist tokens are JWTs, whose payload tells when they expire as a unix timestamp
+pub fn token_expiry(token: &str) -> Option<i64> {
+    let payload = token.split('.').nth(1)?;
+    let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+    claims.get("exp")?.as_i64()
+}
 
-    info!("Configuration with access token is saved!");
-    Ok(cfg_with_token)
+// `max_age` is in hours, 0 means never
+fn is_stale(path: &Path, max_age: u64) -> bool {
+    max_age > 0
+        && matches!(
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok()),
+            Some(age) if age > Duration::from_secs(max_age * 60 * 60)
+        )
 }
 
-pub fn get_user_id(mut cfg: &mut MendoConfig, data_dir: &Path, client: &Client) -> Result<i32> {
+pub fn get_user(cfg: &mut MendoConfig, data_dir: &Path, client: &Client) -> Result<User> {
     let user_profile_path = data_dir.join("user.yml");
     if !user_profile_path.exists() {
         debug!("Local user profile does not exist. Querying to create one...");
-        let query_result = request::query_user(&mut cfg, &client)?;
-        if let Some(viewer_resp) = query_result.data {
-            viewer_resp.viewer.dump_user_info(&user_profile_path)?;
-        }
+        refresh_user_profile(cfg, &user_profile_path, client)?;
+    } else if is_stale(&user_profile_path, cfg.cache.user_max_age) {
+        debug!("Local user profile is older than `cache.user_max_age`. Querying to refresh it...");
+        refresh_user_profile(cfg, &user_profile_path, client)?;
     }
     debug!("Loading user profile...");
     let s = fs::read_to_string(&user_profile_path)?;
-    let user: User = serde_yaml::from_str(&s)?;
-    let user_id = user.id;
+    let mut user: User = serde_yaml::from_str(&s)?;
+    if user.media_list_options.is_none() {
+        debug!("Local user profile has no list options. Querying to refresh it...");
+        refresh_user_profile(cfg, &user_profile_path, client)?;
+        user = serde_yaml::from_str(&fs::read_to_string(&user_profile_path)?)?;
+    }
+    Ok(user)
+}
+
+pub fn get_user_id(cfg: &mut MendoConfig, data_dir: &Path, client: &Client) -> Result<i32> {
+    let user_id = get_user(cfg, data_dir, client)?.id;
     debug!("Got user_id `{}` of authenticated user!", user_id);
     Ok(user_id)
 }
 
-fn get_manga_name<'a>(filename: &'a str, pattern: &'a str) -> Result<&'a str> {
-    let name_re = Regex::new(&pattern)?;
+pub fn get_manga_name<'a>(filename: &'a str, pattern: &'a str) -> Result<&'a str> {
+    let name_re = Regex::new(pattern)?;
     let caps = match name_re.captures(filename) {
         Some(cap) => cap,
         None => {
             error!("Could not get name from archive filename. Try to use the --regexp option?");
-            return Err(anyhow!(
-                "Could not get name from archive filename. Try to use the --regexp option?"
-            ));
+            return Err(MendoError::Parse(format!(
+                "a title from `{}`. Try to use the --regexp option?",
+                filename
+            ))
+            .into());
         }
     };
     Ok(caps.get(1).map_or_else(|| "", |m| m.as_str()))
 }
 
+// Only novels are told apart, everything else is read as manga
+pub fn format_from_filename(filename: &str, novel_extensions: &[String]) -> MediaFormat {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("");
+    if novel_extensions.iter().any(|novel| {
+        novel
+            .trim_start_matches('.')
+            .eq_ignore_ascii_case(extension)
+    }) {
+        MediaFormat::Novel
+    } else {
+        MediaFormat::Manga
+    }
+}
+
+// Covers are cached in `covers` in the data directory, named after the media id
+pub fn get_cover(media: &Media, data_dir: &Path, client: &Client) -> Result<Option<PathBuf>> {
+    let url = match media
+        .cover_image
+        .as_ref()
+        .and_then(|cover| cover.medium.as_ref())
+    {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+    let extension = Path::new(url)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("jpg");
+    let covers_dir = data_dir.join("covers");
+    let cover_path = covers_dir.join(format!("{}.{}", media.media_id, extension));
+    if !cover_path.exists() {
+        debug!("Downloading cover of `{}` from {}", media.media_id, url);
+        fs::create_dir_all(&covers_dir)?;
+        let bytes = client.get(url).send()?.error_for_status()?.bytes()?;
+        fs::write(&cover_path, &bytes)?;
+    }
+    Ok(Some(cover_path))
+}
+
+// Blocks until the notification is closed and returns whether the user clicked "Undo"
+#[cfg(target_family = "unix")]
+pub fn notify_updated(
+    name: &str,
+    progress: &Progress,
+    format: MediaFormat,
+    total: Option<i32>,
+    cover: Option<&Path>,
+    timeout: u32,
+) -> Result<bool> {
+    let summary = match progress.volumes {
+        Some(volumes) => format!("`{}` - Volume `{}` read!", name, volumes),
+        None => format!("`{}` - Chapter `{}` read!", name, progress.chapters),
+    };
+    // `total` counts volumes for novels and chapters for everything else
+    let done = match format {
+        MediaFormat::Novel => progress.volumes.unwrap_or(0),
+        _ => progress.chapters,
+    };
+    let body = match total {
+        Some(total) => format!("Progress: {}/{}", done, total),
+        None => format!("Progress: {}", done),
+    };
+
+    let mut notification = Notification::new();
+    notification
+        .appname("mendo")
+        .timeout(timeout as i32)
+        .summary(summary.as_str())
+        .body(body.as_str())
+        .action("undo", "Undo");
+    if let Some(total) = total.filter(|total| *total > 0) {
+        // Shown as a progress bar by notification daemons that support the `value` hint
+        let percent = (done * 100 / total).min(100);
+        notification.hint(Hint::CustomInt("value".to_string(), percent));
+    }
+    if let Some(cover) = cover {
+        notification.image_path(&cover.display().to_string());
+    }
+
+    let mut undo = false;
+    notification.show()?.wait_for_action(|action| {
+        debug!("Notification closed with action `{}`", action);
+        undo = action == "undo";
+    });
+    Ok(undo)
+}
+
 #[cfg(target_family = "unix")]
-pub fn notify_updated(filename: &str, pattern: &str, progress: i32) -> Result<NotificationHandle> {
-    let name = get_manga_name(&filename, &pattern)?;
-    Ok(Notification::new()
+pub fn notify_error(summary: &str, body: &str) -> Result<()> {
+    Notification::new()
         .appname("mendo")
-        .timeout(2000)
-        .summary(format!("`{}` - Chapter `{}` read!", name, progress).as_str())
-        .show()?)
+        .timeout(10000)
+        .summary(summary)
+        .body(body)
+        .show()?;
+    Ok(())
 }
 
-pub fn get_media_id(
-    mut cfg: &mut MendoConfig,
+// Without a `format`, the title may resolve to a manga as well as to a novel
+pub fn get_media_id_by_name(
+    cfg: &mut MendoConfig,
     data_dir: &Path,
-    filename: &str,
-    pattern: &str,
+    name: &str,
+    format: Option<MediaFormat>,
     client: &Client,
 ) -> Result<i32> {
-    let local_media_data = data_dir.join("media_data.txt");
-    let name = get_manga_name(&filename, &pattern)?;
-    debug!("Got manga name: `{}` using regex", &name);
+    // Novels often have the very title of their manga adaptation, so they are cached apart
+    let (local_media_data, format_in) = match format {
+        Some(MediaFormat::Novel) => (
+            data_dir.join("media_data_novel.txt"),
+            vec![MediaFormat::Novel],
+        ),
+        Some(_) => (
+            data_dir.join("media_data.txt"),
+            vec![MediaFormat::Manga, MediaFormat::OneShot],
+        ),
+        None => (data_dir.join("media_data.txt"), Vec::new()),
+    };
+    match find_local_media_id(&local_media_data, name)? {
+        Some(media_id) => Ok(media_id),
+        None => {
+            debug!("Did not find media_id from local media data. Will now query for it.");
+            let query_result =
+                request::search_media(cfg, name, MediaType::Manga, &format_in, client)?;
+            match query_result.data {
+                Some(media_resp) => {
+                    let media_id = media_resp.media.media_id;
+                    debug!(
+                        "Found media_id: `{}` of manga `{}` from querying the API!",
+                        media_id, &name
+                    );
+                    append_local_data(&local_media_data, name, media_id)?;
+                    Ok(media_id)
+                }
+                None => Err(MendoError::NotFound(name.to_string()).into()),
+            }
+        }
+    }
+}
+
+// Turns a failed response of a REST API into an error. A token that `tracker` rejects
+// becomes `TrackerUnauthorized`, so it exits with the same code as on Anilist.
+pub fn check_status(
+    res: Response,
+    service: &str,
+    tracker: Option<&'static str>,
+) -> Result<Response> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    error!("{} returned {}", service, status);
+    match tracker {
+        Some(name) if status == StatusCode::UNAUTHORIZED => {
+            Err(MendoError::TrackerUnauthorized(name).into())
+        }
+        _ => Err(anyhow!("{} returned {}", service, status)),
+    }
+}
 
+// Title caches of every tracker share the `<name> - mediaId: <id>` line format
+pub fn find_local_media_id(local_media_data: &Path, name: &str) -> Result<Option<i32>> {
     if !local_media_data.exists() {
         debug!(
             "Local media data does not exist, creating one at {}",
             &local_media_data.display()
         );
-        File::create(&local_media_data)?;
+        File::create(local_media_data)?;
     }
 
-    let local_data = fs::read_to_string(&local_media_data)?;
+    let local_data = fs::read_to_string(local_media_data)?;
     debug!(
-        "Attempting to find media_id of manga `{}` from local media data...",
-        &name
+        "Attempting to find media_id of manga `{}` from {}...",
+        &name,
+        &local_media_data.display()
     );
-    let file_re = Regex::new(format!("{} - mediaId: (\\d+)", &name).as_str())?;
-    match file_re.captures(&local_data) {
-        Some(caps) => {
-            let media_id: i32 = caps
-                .get(1)
-                .expect("Safe because of append_local_data")
-                .as_str()
-                .parse()
-                .expect("Could not parse media_id from str to i32");
+    // The whole line, so `Berserk` does not find `Neo Berserk`
+    let file_re = Regex::new(&format!(
+        r"(?m)^{} - mediaId: (\d+)[ \t\r]*$",
+        regex::escape(name)
+    ))?;
+    // A line edited by hand can hold anything, it is searched again then
+    match file_re
+        .captures(&local_data)
+        .map(|caps| caps[1].parse::<i32>())
+    {
+        Some(Ok(media_id)) => {
             debug!(
                 "Found media_id: `{}` of manga `{}` from local media data!",
                 media_id, &name
             );
-            Ok(media_id)
+            Ok(Some(media_id))
         }
-        None => {
-            debug!("Did not find media_id from local media data. Will now query for it.");
-            let query_result = request::search_media(&mut cfg, &name, MediaType::Manga, &client)?;
-            match query_result.data {
-                Some(media_resp) => {
-                    let media_id = media_resp.media.media_id;
-                    debug!(
-                        "Found media_id: `{}` of manga `{}` from querying the API!",
-                        media_id, &name
-                    );
-                    append_local_data(&local_media_data, name, media_id)?;
-                    Ok(media_id)
-                }
-                None => xkcd_unreachable::xkcd_unreachable!(),
-            }
+        Some(Err(e)) => {
+            warn!(
+                "Ignoring unreadable media_id of manga `{}` in {}: {}",
+                &name,
+                &local_media_data.display(),
+                e
+            );
+            Ok(None)
         }
+        None => Ok(None),
     }
 }
 
-fn append_local_data(path: &Path, name: &str, media_id: i32) -> Result<()> {
-    let mut file = OpenOptions::new().append(true).open(&path)?;
+pub fn append_local_data(path: &Path, name: &str, media_id: i32) -> Result<()> {
+    let mut file = OpenOptions::new().append(true).open(path)?;
     writeln!(file, "{} - mediaId: {}", name, media_id)?;
     debug!(
         "Appended received data to local media data at {}",
@@ -221,20 +451,179 @@ fn append_local_data(path: &Path, name: &str, media_id: i32) -> Result<()> {
     Ok(())
 }
 
+pub fn get_media_list(
+    cfg: &mut MendoConfig,
+    user_id: i32,
+    media_id: i32,
+    client: &Client,
+) -> Result<MediaList> {
+    let query_result = match request::query_media_list(
+        cfg,
+        user_id,
+        media_id,
+        MediaType::Manga,
+        Some(MediaListStatus::Dropped),
+        client,
+    ) {
+        Err(e) => match e.downcast_ref::<MendoError>() {
+            Some(MendoError::NoListEntry(media_id)) => {
+                request::create_new_entry(cfg, *media_id, MediaListStatus::Current, 0, client)?;
+                info!("Will now retry to query MediaList...");
+                request::query_media_list(
+                    cfg,
+                    user_id,
+                    *media_id,
+                    MediaType::Manga,
+                    Some(MediaListStatus::Dropped),
+                    client,
+                )?
+            }
+            _ => return Err(e),
+        },
+        query_result => query_result?,
+    };
+
+    match query_result.data {
+        Some(media_list_resp) => Ok(media_list_resp.media_list),
+        None => Err(MendoError::NoListEntry(media_id).into()),
+    }
+}
+
+// Unlike `get_media_list`, never creates an entry, so it is safe for dry runs.
+// Dropped entries are only found when `status_not` does not exclude them.
+pub fn find_media_list(
+    cfg: &mut MendoConfig,
+    user_id: i32,
+    media_id: i32,
+    status_not: Option<MediaListStatus>,
+    client: &Client,
+) -> Result<Option<MediaList>> {
+    match request::query_media_list(cfg, user_id, media_id, MediaType::Manga, status_not, client) {
+        Ok(query_result) => Ok(query_result
+            .data
+            .map(|media_list_resp| media_list_resp.media_list)),
+        Err(e) => match e.downcast_ref::<MendoError>() {
+            Some(MendoError::NoListEntry(_)) => Ok(None),
+            _ => Err(e),
+        },
+    }
+}
+
 pub fn get_eid_and_progress(
-    mut cfg: &mut MendoConfig,
+    cfg: &mut MendoConfig,
     user_id: i32,
     media_id: i32,
     client: &Client,
 ) -> Result<(i32, i32)> {
-    let query_result =
-        request::query_media_list(&mut cfg, user_id, media_id, MediaType::Manga, &client)?;
+    let media_list = get_media_list(cfg, user_id, media_id, client)?;
+    Ok((media_list.entry_id, media_list.progress))
+}
 
-    match query_result.data {
-        Some(media_list_resp) => Ok((
-            media_list_resp.media_list.entry_id,
-            media_list_resp.media_list.progress,
-        )),
-        None => xkcd_unreachable::xkcd_unreachable!(),
+// Collects the text of the direct children of every `record_tag` element,
+// which is all the structure MAL exports and ComicInfo.xml have.
+pub fn read_xml_records(xml: &str, record_tag: &str) -> Result<Vec<HashMap<String, String>>> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut records = Vec::new();
+    let mut record: Option<HashMap<String, String>> = None;
+    let mut field: Option<String> = None;
+
+    loop {
+        match reader.read_event(&mut buf)? {
+            Event::Start(e) => {
+                let tag = reader.decode(e.name())?.to_string();
+                if tag == record_tag {
+                    record = Some(HashMap::new());
+                } else if record.is_some() {
+                    field = Some(tag);
+                }
+            }
+            Event::End(e) => {
+                let tag = reader.decode(e.name())?;
+                if tag == record_tag {
+                    records.extend(record.take());
+                }
+                field = None;
+            }
+            Event::Text(e) => {
+                if let (Some(record), Some(field)) = (record.as_mut(), field.as_ref()) {
+                    record.insert(field.clone(), e.unescape_and_decode(&reader)?);
+                }
+            }
+            Event::CData(e) => {
+                if let (Some(record), Some(field)) = (record.as_mut(), field.as_ref()) {
+                    record.insert(field.clone(), reader.decode(&e)?.to_string());
+                }
+            }
+            Event::Eof => break,
+            _ => (),
+        }
+        buf.clear();
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_cached_media_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("media_data.txt");
+        // Created by the first lookup
+        assert_eq!(find_local_media_id(&path, "Berserk").unwrap(), None);
+        append_local_data(&path, "Berserk", 30002).unwrap();
+        append_local_data(&path, "Vinland Saga (Digital)", 30642).unwrap();
+        assert_eq!(find_local_media_id(&path, "Berserk").unwrap(), Some(30002));
+        // Titles are matched literally, not as a regex
+        assert_eq!(
+            find_local_media_id(&path, "Vinland Saga (Digital)").unwrap(),
+            Some(30642)
+        );
+        assert_eq!(find_local_media_id(&path, "Monster").unwrap(), None);
+        // Only whole lines count, not titles that contain the one looked up
+        fs::write(
+            &path,
+            "Neo Berserk - mediaId: 1\nMonster - mediaId: 2 (old)\r\nBerserk - mediaId: 30002 \r\n",
+        )
+        .unwrap();
+        assert_eq!(find_local_media_id(&path, "Berserk").unwrap(), Some(30002));
+        assert_eq!(find_local_media_id(&path, "Monster").unwrap(), None);
+        assert_eq!(find_local_media_id(&path, "Neo").unwrap(), None);
+    }
+
+    #[test]
+    fn ignores_corrupt_cache_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("media_data.txt");
+        fs::write(&path, "Berserk - mediaId: 99999999999999\n").unwrap();
+        assert_eq!(find_local_media_id(&path, "Berserk").unwrap(), None);
+    }
+
+    #[test]
+    fn tells_novels_by_extension() {
+        let novel_extensions = vec!["epub".to_string(), ".azw3".to_string()];
+        for (filename, format) in &[
+            ("Overlord v05.epub", MediaFormat::Novel),
+            ("Overlord v06.EPUB", MediaFormat::Novel),
+            ("Overlord v07.azw3", MediaFormat::Novel),
+            ("Overlord c050.cbz", MediaFormat::Manga),
+            ("Overlord c050.epub.cbz", MediaFormat::Manga),
+            ("Overlord", MediaFormat::Manga),
+        ] {
+            assert_eq!(
+                format_from_filename(filename, &novel_extensions),
+                *format,
+                "{}",
+                filename
+            );
+        }
+        assert_eq!(
+            format_from_filename("Overlord v05.epub", &[]),
+            MediaFormat::Manga
+        );
     }
 }